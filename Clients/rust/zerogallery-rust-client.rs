@@ -1,14 +1,24 @@
+use bytes::Bytes;
+use futures_util::future::BoxFuture;
+use futures_util::{Stream, StreamExt};
 use reqwest::{
-    header::{HeaderMap, HeaderValue, CONTENT_TYPE, RANGE},
-    multipart::{Form, Part},
-    Body, Client, Response, StatusCode,
+    header::{
+        HeaderMap, HeaderName, HeaderValue, CONTENT_LENGTH, CONTENT_RANGE, CONTENT_TYPE, RANGE,
+        RETRY_AFTER,
+    },
+    Client, Method, Proxy, StatusCode,
 };
 use serde::{Deserialize, Serialize};
-use std::path::Path;
-use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 use tokio::fs::File;
-use tokio::io::{AsyncReadExt, AsyncWriteExt};
-use tokio_util::codec::{BytesCodec, FramedRead};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncSeek, AsyncSeekExt, AsyncWriteExt, ReadBuf};
+use tokio_util::io::ReaderStream;
+
+pub use tokio_util::sync::CancellationToken;
 
 pub type Result<T> = std::result::Result<T, Error>;
 
@@ -20,43 +30,378 @@ pub enum Error {
     #[error("IO error: {0}")]
     Io(#[from] std::io::Error),
     
-    #[error("Unauthorized access")]
-    Unauthorized,
+    #[error("Unauthorized access: {0}")]
+    Unauthorized(Box<ResponseContext>),
     
     #[error("Resource not found: {0}")]
-    NotFound(String),
+    NotFound(Box<ResponseContext>),
+
+    #[error("Empty album name: {0}")]
+    EmptyAlbumName(Box<ResponseContext>),
+
+    #[error("No files for upload: {0}")]
+    NoFilesForUpload(Box<ResponseContext>),
+
+    #[error("Invalid range: {0}")]
+    InvalidRange(Box<ResponseContext>),
     
-    #[error("API error: status={status}, message={message}")]
-    Api { status: u16, message: String },
+    #[error("API error: {0}")]
+    Api(Box<ResponseContext>),
     
-    #[error("Invalid response format")]
-    InvalidResponse,
+    #[error("Invalid response format: {context}: {source}")]
+    InvalidResponse {
+        context: Box<ResponseContext>,
+        #[source]
+        source: serde_json::Error,
+    },
+
+    #[error("Invalid header value: {0}")]
+    InvalidHeader(#[from] reqwest::header::InvalidHeaderValue),
+
+    #[error("No data received within {0:?}")]
+    ReadTimeout(Duration),
+
+    #[error("Invalid client configuration: {0}")]
+    Config(String),
+
+    #[error("Profile error: {0}")]
+    Profile(String),
+
+    #[error("Endpoint unavailable: {0}")]
+    Unavailable(String),
+
+    #[error("Transport error: {0}")]
+    Transport(#[source] Box<dyn std::error::Error + Send + Sync>),
+
+    #[error("Operation cancelled")]
+    Cancelled,
+}
+
+/// Максимальный объем тела ответа, сохраняемый в ошибке
+const ERROR_BODY_LIMIT: usize = 4096;
+
+/// Запрос и ответ сервера, завершившиеся ошибкой
+#[derive(Debug, Clone)]
+pub struct ResponseContext {
+    /// Операция клиента, например `get_album_data`
+    pub operation: &'static str,
+    pub method: Method,
+    pub url: String,
+    pub status: StatusCode,
+    /// Текст тела ответа, не более 4 КиБ
+    pub body: String,
+}
+
+impl ResponseContext {
+    fn new(operation: &'static str, method: Method, url: String, status: StatusCode, body: &[u8]) -> Self {
+        let mut end = body.len().min(ERROR_BODY_LIMIT);
+        while end > 0 && end < body.len() && std::str::from_utf8(&body[..end]).is_err() {
+            end -= 1;
+        }
+        Self {
+            operation,
+            method,
+            url,
+            status,
+            body: String::from_utf8_lossy(&body[..end]).trim().to_string(),
+        }
+    }
+
+    /// Сообщение сервера без кавычек JSON-строки
+    pub fn message(&self) -> &str {
+        self.body.trim_matches('"')
+    }
+}
+
+impl std::fmt::Display for ResponseContext {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} {} returned {}", self.method, self.url, self.status)?;
+        if !self.body.is_empty() {
+            write!(f, ": {}", self.body)?;
+        }
+        Ok(())
+    }
+}
+
+impl Error {
+    /// Ошибка по ответу с неуспешным статусом
+    fn from_response(context: ResponseContext) -> Self {
+        let context = Box::new(context);
+        match context.status {
+            StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => Error::Unauthorized(context),
+            StatusCode::NOT_FOUND => Error::NotFound(context),
+            StatusCode::BAD_REQUEST => match context.message() {
+                "Empty album name" => Error::EmptyAlbumName(context),
+                "No files for upload" => Error::NoFilesForUpload(context),
+                "Invalid range" => Error::InvalidRange(context),
+                _ => Error::Api(context),
+            },
+            _ => Error::Api(context),
+        }
+    }
+
+    /// Запрос и ответ сервера, если ошибку вернул сервер
+    pub fn context(&self) -> Option<&ResponseContext> {
+        match self {
+            Error::Unauthorized(context)
+            | Error::NotFound(context)
+            | Error::EmptyAlbumName(context)
+            | Error::NoFilesForUpload(context)
+            | Error::InvalidRange(context)
+            | Error::Api(context)
+            | Error::InvalidResponse { context, .. } => Some(context),
+            _ => None,
+        }
+    }
+
+    /// HTTP статус ответа сервера
+    pub fn status(&self) -> Option<StatusCode> {
+        self.context().map(|context| context.status)
+    }
+
+    /// Временный сбой: сеть, таймаут или ответ 408, 429, 502, 503, 504.
+    ///
+    /// Из ошибок reqwest временными считаются соединение (`is_connect`), таймаут
+    /// (`is_timeout`), отправка запроса (`is_request`) и обрыв тела ответа после
+    /// заголовков (`is_body`). Построение запроса, перенаправления, статус и
+    /// декодирование тела (`is_decode`) повтором не исправляются.
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            Error::Request(e) => e.is_connect() || e.is_timeout() || e.is_request() || e.is_body(),
+            Error::ReadTimeout(_) | Error::Transport(_) => true,
+            Error::Io(e) => matches!(
+                e.kind(),
+                std::io::ErrorKind::ConnectionReset
+                    | std::io::ErrorKind::ConnectionAborted
+                    | std::io::ErrorKind::BrokenPipe
+                    | std::io::ErrorKind::TimedOut
+                    | std::io::ErrorKind::UnexpectedEof
+            ),
+            Error::Api(context) => is_transient_status(context.status),
+            _ => false,
+        }
+    }
+
+    /// Отказ в доступе: неверный или отсутствующий токен
+    pub fn is_auth(&self) -> bool {
+        matches!(self, Error::Unauthorized(_))
+    }
+
+    /// Обрыв, после которого скачивание продолжается с записанного места:
+    /// временный сбой или ошибка потока тела, в том числе его декодирования
+    fn is_resumable(&self) -> bool {
+        match self {
+            Error::Request(e) => e.is_body() || e.is_decode() || self.is_retryable(),
+            _ => self.is_retryable(),
+        }
+    }
 }
 
 // Модели данных
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-#[serde(rename_all = "camelCase")]
+/// Идентификатор альбома
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct AlbumId(pub i64);
+
+/// Идентификатор записи (файла)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct DataId(pub i64);
+
+macro_rules! id_conversions {
+    ($id:ident) => {
+        impl $id {
+            /// Числовое значение идентификатора
+            pub fn get(self) -> i64 {
+                self.0
+            }
+        }
+
+        impl From<i64> for $id {
+            fn from(id: i64) -> Self {
+                $id(id)
+            }
+        }
+
+        impl From<$id> for i64 {
+            fn from(id: $id) -> Self {
+                id.0
+            }
+        }
+
+        impl std::fmt::Display for $id {
+            fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                self.0.fmt(f)
+            }
+        }
+
+        impl std::str::FromStr for $id {
+            type Err = std::num::ParseIntError;
+
+            fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+                s.parse().map($id)
+            }
+        }
+    };
+}
+
+id_conversions!(AlbumId);
+id_conversions!(DataId);
+
+/// Куда загружать файлы: в альбом или в данные без альбома.
+///
+/// В моделях сервера отсутствие альбома записывается как `-1`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum UploadTarget {
+    /// Данные без альбома
+    #[default]
+    Public,
+    /// Альбом
+    Album(AlbumId),
+}
+
+impl UploadTarget {
+    /// Альбом цели, если есть
+    pub fn album_id(self) -> Option<AlbumId> {
+        match self {
+            UploadTarget::Public => None,
+            UploadTarget::Album(id) => Some(id),
+        }
+    }
+}
+
+impl From<AlbumId> for UploadTarget {
+    fn from(id: AlbumId) -> Self {
+        UploadTarget::Album(id)
+    }
+}
+
+impl From<Option<AlbumId>> for UploadTarget {
+    fn from(id: Option<AlbumId>) -> Self {
+        id.map_or(UploadTarget::Public, UploadTarget::Album)
+    }
+}
+
+/// Необязательный идентификатор в формате сервера: `-1` означает отсутствие
+mod optional_id {
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S, T>(id: &Option<T>, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+        T: Copy + Into<i64>,
+    {
+        serializer.serialize_i64(id.map_or(-1, Into::into))
+    }
+
+    pub fn deserialize<'de, D, T>(deserializer: D) -> Result<Option<T>, D::Error>
+    where
+        D: Deserializer<'de>,
+        T: From<i64>,
+    {
+        let id = Option::<i64>::deserialize(deserializer)?;
+        Ok(id.filter(|id| *id > 0).map(T::from))
+    }
+}
+
+/// Строка, для которой `null` означает пустое значение
+fn nullable_string<'de, D>(deserializer: D) -> std::result::Result<String, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    Ok(Option::<String>::deserialize(deserializer)?.unwrap_or_default())
+}
+
+/// Альбом. Отсутствующие в ответе поля получают значения по умолчанию,
+/// неизвестные поля игнорируются.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
 pub struct AlbumInfo {
-    pub id: i64,
-    pub image_preview_id: i64,
+    pub id: AlbumId,
+    /// Запись, используемая как обложка альбома
+    #[serde(with = "optional_id")]
+    pub image_preview_id: Option<DataId>,
+    #[serde(deserialize_with = "nullable_string")]
     pub name: String,
+    #[serde(deserialize_with = "nullable_string")]
     pub description: String,
     pub is_protected: bool,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-#[serde(rename_all = "camelCase")]
+/// Запись о файле. Отсутствующие в ответе поля получают значения по умолчанию,
+/// неизвестные поля игнорируются.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
 pub struct DataInfo {
-    pub id: i64,
-    pub album_id: i64,
+    pub id: DataId,
+    /// Альбом записи; `None` для данных без альбома
+    #[serde(with = "optional_id")]
+    pub album_id: Option<AlbumId>,
     pub size: i64,
     pub created_timestamp: i64,
+    #[serde(deserialize_with = "nullable_string")]
     pub name: String,
+    #[serde(deserialize_with = "nullable_string")]
     pub extension: String,
+    #[serde(deserialize_with = "nullable_string")]
     pub description: String,
+    #[serde(deserialize_with = "nullable_string")]
     pub mime_type: String,
+    /// Теги через `;`, см. [`DataInfo::tags`]
+    #[serde(deserialize_with = "nullable_string")]
     pub tags: String,
+    /// Для записи есть превью
+    pub has_preview: bool,
+}
+
+/// Вид содержимого записи, как `DataType` на сервере
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DataKind {
+    /// Изображение
+    Image,
+    /// Видео
+    Video,
+    /// Прочие файлы
+    Binary,
+}
+
+/// Расширения изображений, известные серверу
+const IMAGE_EXTENSIONS: &[&str] = &[
+    ".png", ".jpg", ".bmp", ".gif", ".heic", ".ico", ".svg", ".tiff", ".webp",
+    ".dng", ".cr2", ".nef", ".arw", ".orf", ".sr2", ".srf",
+];
+
+/// Расширения видео, известные серверу
+const VIDEO_EXTENSIONS: &[&str] = &[".mov", ".mp4", ".avi", ".webm", ".wmv", ".mkv"];
+
+/// Тег записи
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Tag(String);
+
+impl Tag {
+    /// Тег из строки; пробелы по краям отбрасываются, `;` недопустим
+    pub fn new(tag: impl AsRef<str>) -> Option<Self> {
+        let tag = tag.as_ref().trim();
+        (!tag.is_empty() && !tag.contains(';')).then(|| Tag(tag.to_string()))
+    }
+
+    /// Текст тега
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl std::fmt::Display for Tag {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl AsRef<str> for Tag {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
 }
 
 #[derive(Debug, Clone, Serialize)]
@@ -69,6 +414,34 @@ pub struct CreateAlbumInfo {
 }
 
 impl DataInfo {
+    /// Теги записи без пустых и повторяющихся
+    pub fn tags(&self) -> Vec<Tag> {
+        let mut tags: Vec<Tag> = Vec::new();
+        for tag in self.tags.split(';').filter_map(Tag::new) {
+            if !tags.contains(&tag) {
+                tags.push(tag);
+            }
+        }
+        tags
+    }
+
+    /// Заменить теги записи
+    pub fn set_tags(&mut self, tags: impl IntoIterator<Item = Tag>) {
+        self.tags = tags.into_iter().map(|tag| tag.0).collect::<Vec<_>>().join(";");
+    }
+
+    /// Вид содержимого по расширению, как `DataType` на сервере; MIME-тип не учитывается
+    pub fn kind(&self) -> DataKind {
+        let extension = self.extension.to_lowercase();
+        if IMAGE_EXTENSIONS.contains(&extension.as_str()) {
+            DataKind::Image
+        } else if VIDEO_EXTENSIONS.contains(&extension.as_str()) {
+            DataKind::Video
+        } else {
+            DataKind::Binary
+        }
+    }
+
     /// Получить время создания как SystemTime
     pub fn created_time(&self) -> SystemTime {
         let secs = self.created_timestamp / 1000;
@@ -101,455 +474,5502 @@ impl DataInfo {
 /// Callback для отслеживания прогресса
 pub type ProgressCallback = Box<dyn Fn(u64, u64) + Send + Sync>;
 
-/// Клиент для работы с ZeroGallery API
-pub struct ZeroGalleryClient {
-    client: Client,
-    base_url: String,
-    access_token: Option<String>,
+/// Что делать с недокачанным файлом при отмене или ошибке
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum PartialFile {
+    /// Удалить файл
+    #[default]
+    Remove,
+    /// Оставить полученную часть
+    Keep,
 }
 
-impl ZeroGalleryClient {
-    /// Создать новый клиент
-    pub fn new(base_url: impl Into<String>) -> Self {
-        Self::with_token(base_url, None)
+/// Прогресс загрузки одного файла
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FileProgress<'a> {
+    /// Номер файла в загрузке, с нуля
+    pub index: usize,
+    pub file_name: &'a str,
+    /// Отправлено байт; при повторе запроса отсчет начинается заново
+    pub sent: u64,
+    /// Размер файла, если известен
+    pub total: Option<u64>,
+}
+
+/// Callback для отслеживания прогресса отдельных файлов загрузки
+pub type FileProgressCallback = Box<dyn Fn(FileProgress<'_>) + Send + Sync>;
+
+/// Параметры долгой передачи: отмена, прогресс, судьба недокачанного файла.
+///
+/// Клоны разделяют callback'и прогресса.
+#[derive(Clone, Default)]
+pub struct TransferOptions {
+    cancellation: Option<CancellationToken>,
+    progress: Option<Arc<ProgressCallback>>,
+    file_progress: Option<Arc<FileProgressCallback>>,
+    partial_file: PartialFile,
+}
+
+impl TransferOptions {
+    /// Параметры по умолчанию: без отмены и прогресса, недокачанный файл удаляется
+    pub fn new() -> Self {
+        Self::default()
     }
-    
-    /// Создать клиент с токеном доступа
-    pub fn with_token(base_url: impl Into<String>, access_token: Option<String>) -> Self {
-        let client = Client::builder()
-            .timeout(Duration::from_secs(30))
-            .build()
-            .expect("Failed to create HTTP client");
-            
-        Self {
-            client,
-            base_url: base_url.into().trim_end_matches('/').to_string(),
-            access_token,
-        }
+
+    /// Прервать передачу по токену; операция вернет [`Error::Cancelled`]
+    pub fn with_cancellation(mut self, token: CancellationToken) -> Self {
+        self.cancellation = Some(token);
+        self
     }
-    
-    /// Установить токен доступа
-    pub fn set_access_token(&mut self, token: Option<String>) {
-        self.access_token = token;
+
+    /// Сообщать об общем прогрессе: байт получено или отправлено и общий размер
+    /// (0, если неизвестен)
+    pub fn with_progress(mut self, progress: ProgressCallback) -> Self {
+        self.progress = Some(Arc::new(progress));
+        self
     }
-    
-    /// Создать заголовки с токеном
-    fn create_headers(&self) -> HeaderMap {
-        let mut headers = HeaderMap::new();
-        if let Some(token) = &self.access_token {
-            headers.insert("X-Access-Token", HeaderValue::from_str(token).unwrap());
-        }
-        headers
+
+    /// Сообщать о прогрессе каждого загружаемого файла
+    pub fn with_file_progress(mut self, progress: FileProgressCallback) -> Self {
+        self.file_progress = Some(Arc::new(progress));
+        self
     }
-    
-    /// Обработать ответ API
-    async fn handle_response<T: for<'de> Deserialize<'de>>(
-        &self,
-        response: Response,
-    ) -> Result<T> {
-        match response.status() {
-            StatusCode::OK => {
-                response.json::<T>().await.map_err(|_| Error::InvalidResponse)
-            }
-            StatusCode::UNAUTHORIZED => Err(Error::Unauthorized),
-            StatusCode::NOT_FOUND => Err(Error::NotFound("Resource not found".to_string())),
-            status => {
-                let message = response.text().await.unwrap_or_default();
-                Err(Error::Api {
-                    status: status.as_u16(),
-                    message,
-                })
-            }
-        }
+
+    /// Что делать с недокачанным файлом
+    pub fn with_partial_file(mut self, partial_file: PartialFile) -> Self {
+        self.partial_file = partial_file;
+        self
     }
-    
-    /// Получить версию API
-    pub async fn get_version(&self) -> Result<String> {
-        let url = format!("{}/api/version", self.base_url);
-        let response = self.client
-            .get(&url)
-            .headers(self.create_headers())
-            .send()
-            .await?;
-            
-        match response.status() {
-            StatusCode::OK => Ok(response.text().await?),
-            StatusCode::UNAUTHORIZED => Err(Error::Unauthorized),
-            status => {
-                let message = response.text().await.unwrap_or_default();
-                Err(Error::Api {
-                    status: status.as_u16(),
-                    message,
-                })
-            }
+
+    /// Выполнить операцию с учетом токена отмены
+    async fn run<T>(&self, operation: impl std::future::Future<Output = Result<T>>) -> Result<T> {
+        match &self.cancellation {
+            Some(token) => tokio::select! {
+                biased;
+                _ = token.cancelled() => Err(Error::Cancelled),
+                result = operation => result,
+            },
+            None => operation.await,
         }
     }
-    
-    /// Получить список альбомов
-    pub async fn get_albums(&self) -> Result<Vec<AlbumInfo>> {
-        let url = format!("{}/api/albums", self.base_url);
-        let response = self.client
-            .get(&url)
-            .headers(self.create_headers())
-            .send()
-            .await?;
-            
-        self.handle_response(response).await
+}
+
+impl std::fmt::Debug for TransferOptions {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("TransferOptions")
+            .field("cancellation", &self.cancellation)
+            .field("progress", &self.progress.is_some())
+            .field("file_progress", &self.file_progress.is_some())
+            .field("partial_file", &self.partial_file)
+            .finish()
     }
-    
-    /// Создать новый альбом
-    pub async fn create_album(&self, info: CreateAlbumInfo) -> Result<AlbumInfo> {
-        let url = format!("{}/api/album", self.base_url);
-        let mut headers = self.create_headers();
-        headers.insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
-        
-        let response = self.client
-            .post(&url)
-            .headers(headers)
-            .json(&info)
-            .send()
-            .await?;
-            
-        self.handle_response(response).await
+}
+
+/// Учет отправленных байт загрузки для callback'ов прогресса
+struct UploadTracker {
+    progress: Option<Arc<ProgressCallback>>,
+    file_progress: Option<Arc<FileProgressCallback>>,
+    files: Vec<(String, Option<u64>)>,
+    sent: std::sync::Mutex<Vec<u64>>,
+}
+
+impl UploadTracker {
+    /// Учет для источников
+    fn new<'a>(options: &TransferOptions, sources: impl IntoIterator<Item = &'a UploadSource>) -> Arc<Self> {
+        let files: Vec<(String, Option<u64>)> = sources
+            .into_iter()
+            .map(|source| (source.file_name().to_string(), source.length()))
+            .collect();
+        Arc::new(Self {
+            progress: options.progress.clone(),
+            file_progress: options.file_progress.clone(),
+            sent: std::sync::Mutex::new(vec![0; files.len()]),
+            files,
+        })
     }
-    
-    /// Удалить альбом
-    pub async fn delete_album(&self, album_id: i64) -> Result<()> {
-        let url = format!("{}/api/album/{}", self.base_url, album_id);
-        let response = self.client
-            .delete(&url)
-            .headers(self.create_headers())
-            .send()
-            .await?;
-            
-        match response.status() {
-            StatusCode::OK => Ok(()),
-            StatusCode::UNAUTHORIZED => Err(Error::Unauthorized),
-            status => {
-                let message = response.text().await.unwrap_or_default();
-                Err(Error::Api {
-                    status: status.as_u16(),
-                    message,
-                })
-            }
+
+    /// Учет для источников, если прогресс запрошен
+    fn requested<'a>(
+        options: &TransferOptions,
+        sources: impl IntoIterator<Item = &'a UploadSource>,
+    ) -> Option<Arc<Self>> {
+        (options.progress.is_some() || options.file_progress.is_some()).then(|| Self::new(options, sources))
+    }
+
+    /// Отправлено байт файла `index`
+    fn sent(&self, index: usize) -> u64 {
+        self.sent.lock().unwrap_or_else(|e| e.into_inner())[index]
+    }
+
+    /// Общий размер, 0 если размер какого-либо файла неизвестен
+    fn total(&self) -> u64 {
+        self.files
+            .iter()
+            .map(|(_, length)| *length)
+            .sum::<Option<u64>>()
+            .unwrap_or(0)
+    }
+
+    /// Учесть `bytes` байт файла `index`; `None` начинает файл заново
+    fn advance(&self, index: usize, bytes: Option<u64>) {
+        let (file_sent, total_sent) = {
+            let mut sent = self.sent.lock().unwrap_or_else(|e| e.into_inner());
+            sent[index] = bytes.map_or(0, |bytes| sent[index] + bytes);
+            (sent[index], sent.iter().sum::<u64>())
+        };
+        if let Some(callback) = &self.file_progress {
+            let (file_name, total) = &self.files[index];
+            callback(FileProgress {
+                index,
+                file_name,
+                sent: file_sent,
+                total: *total,
+            });
+        }
+        if let Some(callback) = &self.progress {
+            callback(total_sent, self.total());
         }
     }
-    
-    /// Получить данные без альбомов
-    pub async fn get_data_without_albums(&self) -> Result<Vec<DataInfo>> {
-        let url = format!("{}/api/data", self.base_url);
-        let response = self.client
-            .get(&url)
-            .headers(self.create_headers())
-            .send()
-            .await?;
-            
-        self.handle_response(response).await
+
+    /// Поток файла `index`, учитывающий отправленные байты
+    fn track(self: &Arc<Self>, index: usize, stream: RequestStream) -> RequestStream {
+        self.advance(index, None);
+        let tracker = self.clone();
+        Box::pin(stream.inspect(move |chunk| {
+            if let Ok(bytes) = chunk {
+                tracker.advance(index, Some(bytes.len() as u64));
+            }
+        }))
     }
-    
-    /// Получить данные альбома
-    pub async fn get_album_data(&self, album_id: i64) -> Result<Vec<DataInfo>> {
-        let url = format!("{}/api/album/{}/data", self.base_url, album_id);
-        let response = self.client
-            .get(&url)
-            .headers(self.create_headers())
-            .send()
-            .await?;
-            
-        self.handle_response(response).await
+}
+
+/// Размер порции при чтении загружаемого файла
+const UPLOAD_CHUNK_SIZE: usize = 64 * 1024;
+
+/// Источник загружаемого файла: данные в памяти, файл на диске или `AsyncRead`.
+///
+/// Содержимое не читается в память целиком, а передается в тело запроса потоком.
+/// Данные в памяти и файл открываются заново на каждую попытку, поэтому их загрузку
+/// можно повторять; `AsyncRead` отправляется один раз.
+pub struct UploadSource {
+    file_name: String,
+    content: UploadContent,
+}
+
+enum UploadContent {
+    Bytes(Bytes),
+    Path(PathBuf),
+    Reader {
+        reader: std::sync::Mutex<Option<Pin<Box<dyn AsyncRead + Send>>>>,
+        length: Option<u64>,
+    },
+}
+
+impl UploadSource {
+    /// Данные в памяти
+    pub fn from_bytes(file_name: impl Into<String>, data: impl Into<Bytes>) -> Self {
+        Self {
+            file_name: file_name.into(),
+            content: UploadContent::Bytes(data.into()),
+        }
     }
-    
-    /// Загрузить файл
-    pub async fn upload_file<P: AsRef<Path>>(
-        &self,
-        file_path: P,
-        album_id: i64,
-    ) -> Result<i64> {
-        let file_path = file_path.as_ref();
-        let file_name = file_path
+
+    /// Файл на диске; имя файла берется из пути
+    pub fn from_path(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let file_name = path
             .file_name()
             .and_then(|n| n.to_str())
             .ok_or_else(|| Error::Io(std::io::Error::new(
                 std::io::ErrorKind::InvalidInput,
                 "Invalid file name",
             )))?;
-            
-        let mut file = File::open(file_path).await?;
-        let mut contents = Vec::new();
-        file.read_to_end(&mut contents).await?;
-        
-        self.upload_file_data(&contents, file_name, album_id).await
+        Ok(Self {
+            file_name: file_name.to_string(),
+            content: UploadContent::Path(path.to_path_buf()),
+        })
     }
-    
-    /// Загрузить файл из данных
-    pub async fn upload_file_data(
-        &self,
-        data: &[u8],
-        filename: &str,
-        album_id: i64,
-    ) -> Result<i64> {
-        let url = if album_id > 0 {
-            format!("{}/api/upload/{}", self.base_url, album_id)
-        } else {
-            format!("{}/api/upload", self.base_url)
-        };
-        
-        let part = Part::bytes(data.to_vec())
-            .file_name(filename.to_string());
-            
-        let form = Form::new().part("file", part);
-        
-        let response = self.client
-            .post(&url)
-            .headers(self.create_headers())
-            .multipart(form)
-            .send()
-            .await?;
-            
-        self.handle_response(response).await
-    }
-    
-    /// Загрузить несколько файлов
-    pub async fn upload_multiple_files<P: AsRef<Path>>(
-        &self,
-        file_paths: &[P],
-        album_id: i64,
-    ) -> Result<Vec<i64>> {
-        let url = if album_id > 0 {
-            format!("{}/api/upload/{}", self.base_url, album_id)
-        } else {
-            format!("{}/api/upload", self.base_url)
-        };
-        
-        let mut form = Form::new();
-        
-        for file_path in file_paths {
-            let file_path = file_path.as_ref();
-            let file_name = file_path
-                .file_name()
-                .and_then(|n| n.to_str())
-                .ok_or_else(|| Error::Io(std::io::Error::new(
-                    std::io::ErrorKind::InvalidInput,
-                    "Invalid file name",
-                )))?;
-                
-            let mut file = File::open(file_path).await?;
-            let mut contents = Vec::new();
-            file.read_to_end(&mut contents).await?;
-            
-            let part = Part::bytes(contents)
-                .file_name(file_name.to_string());
-            form = form.part("files", part);
+
+    /// Произвольный поток. Если длина известна, тело запроса получает `Content-Length`
+    /// и поток, оборвавшийся раньше, завершает загрузку ошибкой.
+    pub fn from_reader(
+        file_name: impl Into<String>,
+        reader: impl AsyncRead + Send + 'static,
+        length: Option<u64>,
+    ) -> Self {
+        Self {
+            file_name: file_name.into(),
+            content: UploadContent::Reader {
+                reader: std::sync::Mutex::new(Some(Box::pin(reader))),
+                length,
+            },
         }
-        
-        let response = self.client
-            .post(&url)
-            .headers(self.create_headers())
-            .multipart(form)
-            .send()
-            .await?;
-            
-        self.handle_response(response).await
+    }
+
+    /// Задать имя файла на сервере
+    pub fn with_file_name(mut self, file_name: impl Into<String>) -> Self {
+        self.file_name = file_name.into();
+        self
+    }
+
+    /// Имя файла на сервере
+    pub fn file_name(&self) -> &str {
+        &self.file_name
+    }
+
+    /// Размер содержимого, если известен; для файла читается из метаданных
+    pub fn length(&self) -> Option<u64> {
+        match &self.content {
+            UploadContent::Bytes(data) => Some(data.len() as u64),
+            UploadContent::Path(path) => std::fs::metadata(path).ok().map(|metadata| metadata.len()),
+            UploadContent::Reader { length, .. } => *length,
+        }
+    }
+
+    /// Содержимое можно отправить повторно
+    pub fn is_replayable(&self) -> bool {
+        !matches!(self.content, UploadContent::Reader { .. })
+    }
+
+    /// Открыть содержимое как поток с длиной, если она известна
+    fn open(&self) -> Result<(RequestStream, Option<u64>)> {
+        match &self.content {
+            UploadContent::Bytes(data) => {
+                let data = data.clone();
+                let length = data.len() as u64;
+                Ok((Box::pin(futures_util::stream::once(async move { Ok(data) })), Some(length)))
+            }
+            UploadContent::Path(path) => {
+                let file = std::fs::File::open(path)?;
+                let length = file.metadata()?.len();
+                Ok((read_exact_length(File::from_std(file), length), Some(length)))
+            }
+            UploadContent::Reader { reader, length } => {
+                let reader = reader
+                    .lock()
+                    .unwrap_or_else(|e| e.into_inner())
+                    .take()
+                    .ok_or_else(|| Error::Config(format!(
+                        "upload source '{}' is a reader and was already consumed",
+                        self.file_name
+                    )))?;
+                let stream: RequestStream = match length {
+                    Some(length) => read_exact_length(reader, *length),
+                    None => Box::pin(ReaderStream::with_capacity(reader, UPLOAD_CHUNK_SIZE)),
+                };
+                Ok((stream, *length))
+            }
+        }
+    }
+}
+
+impl std::fmt::Debug for UploadSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let content = match &self.content {
+            UploadContent::Bytes(data) => format!("Bytes({} bytes)", data.len()),
+            UploadContent::Path(path) => format!("Path({})", path.display()),
+            UploadContent::Reader { length, .. } => format!("Reader(length: {:?})", length),
+        };
+        f.debug_struct("UploadSource")
+            .field("file_name", &self.file_name)
+            .field("content", &content)
+            .finish()
+    }
+}
+
+/// Поток ровно из `length` байт: лишнее отбрасывается, нехватка - ошибка
+fn read_exact_length(reader: impl AsyncRead + Send + Unpin + 'static, length: u64) -> RequestStream {
+    let stream = ReaderStream::with_capacity(reader.take(length), UPLOAD_CHUNK_SIZE);
+    Box::pin(futures_util::stream::unfold(
+        (stream, 0u64, false),
+        move |(mut stream, sent, done)| async move {
+            if done {
+                return None;
+            }
+            match stream.next().await {
+                Some(Ok(chunk)) => {
+                    let sent = sent + chunk.len() as u64;
+                    Some((Ok(chunk), (stream, sent, false)))
+                }
+                Some(Err(error)) => Some((Err(error), (stream, sent, true))),
+                None if sent < length => {
+                    let error = std::io::Error::new(
+                        std::io::ErrorKind::UnexpectedEof,
+                        format!("upload source ended after {} of {} bytes", sent, length),
+                    );
+                    Some((Err(error), (stream, sent, true)))
+                }
+                None => None,
+            }
+        },
+    ))
+}
+
+/// Заголовок с токеном загрузки (или мастер-токеном)
+pub const UPLOAD_TOKEN_HEADER: &str = "X-ZERO-UPLOAD-TOKEN";
+
+/// Заголовок с токеном доступа к альбому
+pub const ACCESS_TOKEN_HEADER: &str = "X-ZERO-ACCESS-TOKEN";
+
+/// Учетные данные клиента
+#[derive(Clone, Default, PartialEq, Eq)]
+pub struct Credentials {
+    /// Токен записи (`api_write_token` на сервере)
+    pub upload_token: Option<String>,
+    /// Токен доступа к защищенному альбому
+    pub access_token: Option<String>,
+    /// Мастер-токен (`api_master_token` на сервере)
+    pub master_token: Option<String>,
+}
+
+impl Credentials {
+    /// Пустые учетные данные
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Задать токен записи
+    pub fn with_upload_token(mut self, token: impl Into<String>) -> Self {
+        self.upload_token = Some(token.into());
+        self
+    }
+
+    /// Задать токен доступа к альбому
+    pub fn with_access_token(mut self, token: impl Into<String>) -> Self {
+        self.access_token = Some(token.into());
+        self
+    }
+
+    /// Задать мастер-токен
+    pub fn with_master_token(mut self, token: impl Into<String>) -> Self {
+        self.master_token = Some(token.into());
+        self
+    }
+
+    /// Токен для заголовка загрузки: сервер сверяет мастер-токен по тому же заголовку
+    fn upload_header_token(&self) -> Option<&str> {
+        self.master_token
+            .as_deref()
+            .or(self.upload_token.as_deref())
+    }
+}
+
+impl std::fmt::Debug for Credentials {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mask = |token: &Option<String>| token.as_ref().map(|_| "***");
+        f.debug_struct("Credentials")
+            .field("upload_token", &mask(&self.upload_token))
+            .field("access_token", &mask(&self.access_token))
+            .field("master_token", &mask(&self.master_token))
+            .finish()
+    }
+}
+
+/// Какие токены нужны запросу
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum AuthScope {
+    /// Без токенов
+    Public,
+    /// Чтение: токен доступа, мастер-токен при наличии
+    Read,
+    /// Создание альбома: токен записи
+    Create,
+    /// Загрузка и удаление: токен записи и токен доступа к альбому
+    Write,
+}
+
+/// Реестр токенов доступа к альбомам.
+///
+/// Помимо токенов хранит принадлежность записей альбомам: она запоминается из списков
+/// данных альбомов и ответов на загрузку, чтобы запросы по идентификатору записи
+/// отправлялись с токеном альбома-владельца. Клоны разделяют одно состояние.
+#[derive(Clone, Default)]
+pub struct AlbumCredentials {
+    inner: Arc<RwLock<AlbumCredentialsInner>>,
+}
+
+#[derive(Default)]
+struct AlbumCredentialsInner {
+    tokens: HashMap<AlbumId, String>,
+    owners: HashMap<DataId, AlbumId>,
+}
+
+impl AlbumCredentials {
+    /// Пустой реестр
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Добавить токен альбома, вернуть предыдущий
+    pub fn insert(&self, album_id: AlbumId, token: impl Into<String>) -> Option<String> {
+        self.write().tokens.insert(album_id, token.into())
+    }
+
+    /// Удалить токен альбома
+    pub fn remove(&self, album_id: AlbumId) -> Option<String> {
+        self.write().tokens.remove(&album_id)
+    }
+
+    /// Токен альбома
+    pub fn token(&self, album_id: AlbumId) -> Option<String> {
+        self.read().tokens.get(&album_id).cloned()
+    }
+
+    /// Альбом, которому принадлежит запись
+    pub fn album_of(&self, data_id: DataId) -> Option<AlbumId> {
+        self.read().owners.get(&data_id).copied()
+    }
+
+    /// Запомнить, что запись принадлежит альбому
+    pub fn remember_data(&self, data_id: DataId, album_id: AlbumId) {
+        self.write().owners.insert(data_id, album_id);
+    }
+
+    /// Запомнить принадлежность записей из списка
+    pub fn remember_all<'a>(&self, items: impl IntoIterator<Item = &'a DataInfo>) {
+        let mut inner = self.write();
+        for item in items {
+            if let Some(album_id) = item.album_id {
+                inner.owners.insert(item.id, album_id);
+            }
+        }
+    }
+
+    /// Забыть запись
+    pub fn forget_data(&self, data_id: DataId) {
+        self.write().owners.remove(&data_id);
+    }
+
+    /// Количество альбомов с токенами
+    pub fn len(&self) -> usize {
+        self.read().tokens.len()
+    }
+
+    /// Реестр не содержит токенов
+    pub fn is_empty(&self) -> bool {
+        self.read().tokens.is_empty()
+    }
+
+    fn read(&self) -> std::sync::RwLockReadGuard<'_, AlbumCredentialsInner> {
+        self.inner.read().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn write(&self) -> std::sync::RwLockWriteGuard<'_, AlbumCredentialsInner> {
+        self.inner.write().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl std::fmt::Debug for AlbumCredentials {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let inner = self.read();
+        let mut albums: Vec<_> = inner.tokens.keys().copied().collect();
+        albums.sort_unstable();
+        f.debug_struct("AlbumCredentials")
+            .field("albums", &albums)
+            .field("known_data", &inner.owners.len())
+            .finish()
+    }
+}
+
+/// Таймаут установки соединения по умолчанию
+pub const DEFAULT_CONNECT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// User-Agent по умолчанию
+pub const DEFAULT_USER_AGENT: &str = concat!("zerogallery-rust/", env!("CARGO_PKG_VERSION"));
+
+/// Предпочтение версии HTTP
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum HttpVersionPref {
+    /// Согласование по ALPN
+    #[default]
+    Auto,
+    /// Только HTTP/1.1
+    Http1Only,
+    /// HTTP/2 без согласования (prior knowledge)
+    Http2PriorKnowledge,
+}
+
+// TLS
+
+/// Реализация TLS; доступные варианты определяются cargo-фичами
+#[cfg(any(feature = "default-tls", feature = "rustls-tls"))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TlsBackend {
+    /// Системная библиотека (фича `default-tls`)
+    #[cfg(feature = "default-tls")]
+    Native,
+    /// rustls (фича `rustls-tls`)
+    #[cfg(feature = "rustls-tls")]
+    Rustls,
+}
+
+#[cfg(any(feature = "default-tls", feature = "rustls-tls"))]
+impl Default for TlsBackend {
+    fn default() -> Self {
+        #[cfg(feature = "default-tls")]
+        return TlsBackend::Native;
+        #[cfg(not(feature = "default-tls"))]
+        return TlsBackend::Rustls;
+    }
+}
+
+/// Клиентский сертификат для mTLS: цепочка сертификатов и ключ PKCS#8 в PEM
+#[cfg(any(feature = "default-tls", feature = "rustls-tls"))]
+#[derive(Clone)]
+pub struct ClientIdentity {
+    cert_chain_pem: Vec<u8>,
+    key_pem: Vec<u8>,
+}
+
+#[cfg(any(feature = "default-tls", feature = "rustls-tls"))]
+impl ClientIdentity {
+    /// Сертификат и ключ из PEM
+    pub fn from_pem(cert_chain_pem: impl Into<Vec<u8>>, key_pem: impl Into<Vec<u8>>) -> Self {
+        Self {
+            cert_chain_pem: cert_chain_pem.into(),
+            key_pem: key_pem.into(),
+        }
+    }
+
+    /// Сертификат и ключ из PEM-файлов
+    pub fn from_pem_files(cert_chain_path: impl AsRef<Path>, key_path: impl AsRef<Path>) -> Result<Self> {
+        Ok(Self::from_pem(
+            std::fs::read(cert_chain_path)?,
+            std::fs::read(key_path)?,
+        ))
+    }
+}
+
+#[cfg(any(feature = "default-tls", feature = "rustls-tls"))]
+impl std::fmt::Debug for ClientIdentity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ClientIdentity")
+            .field("cert_chain_pem", &format_args!("{} bytes", self.cert_chain_pem.len()))
+            .finish_non_exhaustive()
+    }
+}
+
+/// Источник PEM-данных
+#[cfg(any(feature = "default-tls", feature = "rustls-tls"))]
+#[derive(Debug, Clone)]
+enum PemSource {
+    Bytes(Vec<u8>),
+    File(PathBuf),
+}
+
+#[cfg(any(feature = "default-tls", feature = "rustls-tls"))]
+impl PemSource {
+    fn load(&self) -> Result<Vec<u8>> {
+        match self {
+            PemSource::Bytes(pem) => Ok(pem.clone()),
+            PemSource::File(path) => std::fs::read(path).map_err(|e| {
+                Error::Config(format!("cannot read CA bundle {}: {}", path.display(), e))
+            }),
+        }
+    }
+}
+
+/// Настройки TLS построителя
+#[cfg(any(feature = "default-tls", feature = "rustls-tls"))]
+#[derive(Debug, Clone, Default)]
+struct TlsOptions {
+    backend: Option<TlsBackend>,
+    root_certificates: Vec<PemSource>,
+    disable_built_in_roots: bool,
+    identity: Option<ClientIdentity>,
+    pins: Vec<String>,
+}
+
+#[cfg(any(feature = "default-tls", feature = "rustls-tls"))]
+impl TlsOptions {
+    fn is_default(&self) -> bool {
+        self.backend.is_none()
+            && self.root_certificates.is_empty()
+            && !self.disable_built_in_roots
+            && self.identity.is_none()
+            && self.pins.is_empty()
+    }
+
+    /// Применить настройки к построителю `reqwest`
+    #[cfg_attr(not(feature = "rustls-tls"), allow(unused_variables))]
+    fn apply(&self, builder: reqwest::ClientBuilder, http_version: HttpVersionPref) -> Result<reqwest::ClientBuilder> {
+        let backend = self.backend.unwrap_or_default();
+
+        if !self.pins.is_empty() {
+            #[cfg(feature = "rustls-tls")]
+            if backend == TlsBackend::Rustls {
+                let config = pinning::client_config(self, http_version)?;
+                return Ok(builder.use_preconfigured_tls(config));
+            }
+            return Err(Error::Config(
+                "certificate pinning requires the rustls TLS backend".to_string(),
+            ));
+        }
+
+        let mut builder = match backend {
+            #[cfg(feature = "default-tls")]
+            TlsBackend::Native => builder.use_native_tls(),
+            #[cfg(feature = "rustls-tls")]
+            TlsBackend::Rustls => builder.use_rustls_tls(),
+        };
+        for source in &self.root_certificates {
+            let certificates = reqwest::Certificate::from_pem_bundle(&source.load()?)?;
+            if certificates.is_empty() {
+                return Err(Error::Config("no certificates found in CA bundle".to_string()));
+            }
+            for certificate in certificates {
+                builder = builder.add_root_certificate(certificate);
+            }
+        }
+        if self.disable_built_in_roots {
+            builder = builder.tls_built_in_root_certs(false);
+        }
+        if let Some(identity) = &self.identity {
+            let identity = match backend {
+                #[cfg(feature = "default-tls")]
+                TlsBackend::Native => {
+                    reqwest::Identity::from_pkcs8_pem(&identity.cert_chain_pem, &identity.key_pem)?
+                }
+                #[cfg(feature = "rustls-tls")]
+                TlsBackend::Rustls => {
+                    reqwest::Identity::from_pem(&[&identity.cert_chain_pem[..], b"\n", &identity.key_pem[..]].concat())?
+                }
+            };
+            builder = builder.identity(identity);
+        }
+        Ok(builder)
+    }
+}
+
+/// Закрепление сертификатов сервера поверх обычной проверки цепочки
+#[cfg(feature = "rustls-tls")]
+mod pinning {
+    use super::{Error, HttpVersionPref, Result, TlsOptions};
+    use rustls::client::{ServerCertVerified, ServerCertVerifier, WebPkiVerifier};
+    use rustls::{Certificate, ClientConfig, OwnedTrustAnchor, PrivateKey, RootCertStore, ServerName};
+    use sha2::{Digest, Sha256};
+    use std::sync::Arc;
+    use std::time::SystemTime;
+
+    struct PinnedVerifier {
+        inner: WebPkiVerifier,
+        pins: Vec<[u8; 32]>,
+    }
+
+    impl ServerCertVerifier for PinnedVerifier {
+        fn verify_server_cert(
+            &self,
+            end_entity: &Certificate,
+            intermediates: &[Certificate],
+            server_name: &ServerName,
+            scts: &mut dyn Iterator<Item = &[u8]>,
+            ocsp_response: &[u8],
+            now: SystemTime,
+        ) -> std::result::Result<ServerCertVerified, rustls::Error> {
+            self.inner
+                .verify_server_cert(end_entity, intermediates, server_name, scts, ocsp_response, now)?;
+            let fingerprint: [u8; 32] = Sha256::digest(&end_entity.0).into();
+            if self.pins.contains(&fingerprint) {
+                Ok(ServerCertVerified::assertion())
+            } else {
+                Err(rustls::Error::General(
+                    "server certificate does not match any pinned fingerprint".to_string(),
+                ))
+            }
+        }
+    }
+
+    /// Разобрать SHA-256 отпечаток в hex, допускаются разделители `:`
+    pub(super) fn parse_fingerprint(value: &str) -> Result<[u8; 32]> {
+        let hex: String = value.chars().filter(|c| *c != ':').collect();
+        let invalid = || Error::Config(format!("invalid SHA-256 fingerprint '{}'", value));
+        if hex.len() != 64 || !hex.is_ascii() {
+            return Err(invalid());
+        }
+        let mut fingerprint = [0u8; 32];
+        for (i, byte) in fingerprint.iter_mut().enumerate() {
+            *byte = u8::from_str_radix(&hex[i * 2..i * 2 + 2], 16).map_err(|_| invalid())?;
+        }
+        Ok(fingerprint)
+    }
+
+    fn tls_error(message: impl std::fmt::Display) -> Error {
+        Error::Config(format!("TLS configuration: {}", message))
+    }
+
+    pub(super) fn client_config(options: &TlsOptions, http_version: HttpVersionPref) -> Result<ClientConfig> {
+        let mut roots = RootCertStore::empty();
+        if !options.disable_built_in_roots {
+            roots.add_trust_anchors(webpki_roots::TLS_SERVER_ROOTS.iter().map(|anchor| {
+                OwnedTrustAnchor::from_subject_spki_name_constraints(
+                    anchor.subject,
+                    anchor.spki,
+                    anchor.name_constraints,
+                )
+            }));
+        }
+        for source in &options.root_certificates {
+            let pem = source.load()?;
+            let certificates = rustls_pemfile::certs(&mut &pem[..]).map_err(tls_error)?;
+            if certificates.is_empty() {
+                return Err(tls_error("no certificates found in CA bundle"));
+            }
+            for der in certificates {
+                roots.add(&Certificate(der)).map_err(tls_error)?;
+            }
+        }
+
+        let pins = options
+            .pins
+            .iter()
+            .map(|pin| parse_fingerprint(pin))
+            .collect::<Result<Vec<_>>>()?;
+        let verifier = PinnedVerifier {
+            inner: WebPkiVerifier::new(roots, None),
+            pins,
+        };
+        let builder = ClientConfig::builder()
+            .with_safe_defaults()
+            .with_custom_certificate_verifier(Arc::new(verifier));
+
+        let mut config = match &options.identity {
+            Some(identity) => {
+                let chain = rustls_pemfile::certs(&mut &identity.cert_chain_pem[..])
+                    .map_err(tls_error)?
+                    .into_iter()
+                    .map(Certificate)
+                    .collect();
+                let key = rustls_pemfile::read_all(&mut &identity.key_pem[..])
+                    .map_err(tls_error)?
+                    .into_iter()
+                    .find_map(|item| match item {
+                        rustls_pemfile::Item::PKCS8Key(der)
+                        | rustls_pemfile::Item::RSAKey(der)
+                        | rustls_pemfile::Item::ECKey(der) => Some(PrivateKey(der)),
+                        _ => None,
+                    })
+                    .ok_or_else(|| tls_error("no private key found in client identity"))?;
+                builder.with_client_auth_cert(chain, key).map_err(tls_error)?
+            }
+            None => builder.with_no_client_auth(),
+        };
+        config.alpn_protocols = match http_version {
+            HttpVersionPref::Auto => vec![b"h2".to_vec(), b"http/1.1".to_vec()],
+            HttpVersionPref::Http1Only => vec![b"http/1.1".to_vec()],
+            HttpVersionPref::Http2PriorKnowledge => vec![b"h2".to_vec()],
+        };
+        Ok(config)
+    }
+}
+
+/// Построитель клиента ZeroGallery.
+///
+/// Общий таймаут по умолчанию не задан, чтобы не обрывать загрузку больших файлов;
+/// для зависших соединений используйте [`read_timeout`](Self::read_timeout).
+pub struct ZeroGalleryClientBuilder {
+    base_url: String,
+    credentials: Credentials,
+    album_credentials: AlbumCredentials,
+    http_client: Option<Client>,
+    transport: Option<Arc<dyn Transport>>,
+    #[cfg(feature = "tower")]
+    layers: Vec<WrapTransport>,
+    read_timeout: Option<Duration>,
+    retry: RetryPolicy,
+    endpoints: Vec<Endpoint>,
+    circuit_breaker: CircuitBreakerPolicy,
+    rate_limiter: Option<RateLimiter>,
+    metrics: Option<ClientMetrics>,
+    connect_timeout: Option<Duration>,
+    timeout: Option<Duration>,
+    user_agent: Option<String>,
+    proxy: Option<String>,
+    proxy_auth: Option<(String, String)>,
+    default_headers: HeaderMap,
+    pool_max_idle_per_host: Option<usize>,
+    pool_idle_timeout: Option<Duration>,
+    http_version: HttpVersionPref,
+    #[cfg(any(feature = "default-tls", feature = "rustls-tls"))]
+    tls: TlsOptions,
+}
+
+impl ZeroGalleryClientBuilder {
+    /// Построитель по профилю
+    pub fn from_profile(profile: &Profile) -> Result<Self> {
+        let url = profile
+            .url
+            .clone()
+            .ok_or_else(|| Error::Profile("profile has no url".to_string()))?;
+        Ok(Self::new(url)
+            .credentials(profile.credentials())
+            .album_credentials(profile.album_credentials()?))
+    }
+
+    /// Новый построитель для сервера `base_url`
+    pub fn new(base_url: impl Into<String>) -> Self {
+        Self {
+            base_url: base_url.into(),
+            credentials: Credentials::default(),
+            album_credentials: AlbumCredentials::default(),
+            http_client: None,
+            transport: None,
+            #[cfg(feature = "tower")]
+            layers: Vec::new(),
+            read_timeout: None,
+            retry: RetryPolicy::default(),
+            endpoints: Vec::new(),
+            circuit_breaker: CircuitBreakerPolicy::default(),
+            rate_limiter: None,
+            metrics: None,
+            connect_timeout: None,
+            timeout: None,
+            user_agent: None,
+            proxy: None,
+            proxy_auth: None,
+            default_headers: HeaderMap::new(),
+            pool_max_idle_per_host: None,
+            pool_idle_timeout: None,
+            http_version: HttpVersionPref::Auto,
+            #[cfg(any(feature = "default-tls", feature = "rustls-tls"))]
+            tls: TlsOptions::default(),
+        }
+    }
+
+    /// Учетные данные
+    pub fn credentials(mut self, credentials: Credentials) -> Self {
+        self.credentials = credentials;
+        self
+    }
+
+    /// Реестр токенов альбомов
+    pub fn album_credentials(mut self, album_credentials: AlbumCredentials) -> Self {
+        self.album_credentials = album_credentials;
+        self
+    }
+
+    /// Использовать готовый `reqwest::Client`.
+    ///
+    /// Настройки транспорта построителя с ним несовместимы, кроме `read_timeout`.
+    pub fn http_client(mut self, client: Client) -> Self {
+        self.http_client = Some(client);
+        self
+    }
+
+    /// Использовать собственный транспорт вместо `reqwest`.
+    ///
+    /// Настройки транспорта построителя с ним несовместимы, кроме `read_timeout`.
+    pub fn transport(mut self, transport: impl Transport + 'static) -> Self {
+        self.transport = Some(Arc::new(transport));
+        self
+    }
+
+    /// Обернуть транспорт слоем tower.
+    ///
+    /// Слои применяются в порядке добавления, как в `tower::ServiceBuilder`: первый
+    /// добавленный слой внешний. Слои видят все запросы, включая потоковые.
+    #[cfg(feature = "tower")]
+    pub fn layer<L>(mut self, layer: L) -> Self
+    where
+        L: tower::Layer<service::TransportService> + Send + 'static,
+        L::Service: tower::Service<ApiRequest, Response = ApiResponse> + Clone + Send + 'static,
+        <L::Service as tower::Service<ApiRequest>>::Error: Into<BoxError>,
+        <L::Service as tower::Service<ApiRequest>>::Future: Send + 'static,
+    {
+        self.layers.push(Box::new(move |inner: Arc<dyn Transport>| -> Arc<dyn Transport> {
+            let service = layer.layer(service::TransportService::new(inner));
+            Arc::new(service::ServiceTransport::new(service))
+        }));
+        self
+    }
+
+    /// Таймаут установки соединения (по умолчанию 30 секунд)
+    pub fn connect_timeout(mut self, timeout: Duration) -> Self {
+        self.connect_timeout = Some(timeout);
+        self
+    }
+
+    /// Максимальная пауза в получении ответа: ожидание заголовков и между фрагментами тела
+    pub fn read_timeout(mut self, timeout: Duration) -> Self {
+        self.read_timeout = Some(timeout);
+        self
+    }
+
+    /// Политика повторов (по умолчанию [`RetryPolicy::default`])
+    pub fn retry_policy(mut self, policy: RetryPolicy) -> Self {
+        self.retry = policy;
+        self
+    }
+
+    /// Дополнительная точка подключения, например реплика только для чтения.
+    ///
+    /// Основной адрес построителя всегда первая точка и принимает запись.
+    pub fn endpoint(mut self, endpoint: Endpoint) -> Self {
+        self.endpoints.push(endpoint);
+        self
+    }
+
+    /// Настройки автомата отключения для нескольких точек подключения
+    pub fn circuit_breaker(mut self, policy: CircuitBreakerPolicy) -> Self {
+        self.circuit_breaker = policy;
+        self
+    }
+
+    /// Ограничитель скорости; клоны одного ограничителя делят бюджет между клиентами
+    pub fn rate_limiter(mut self, limiter: RateLimiter) -> Self {
+        self.rate_limiter = Some(limiter);
+        self
+    }
+
+    /// Общий набор метрик; по умолчанию у каждого клиента свой
+    pub fn metrics(mut self, metrics: ClientMetrics) -> Self {
+        self.metrics = Some(metrics);
+        self
+    }
+
+    /// Общий таймаут запроса, включая передачу тела
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Заголовок User-Agent
+    pub fn user_agent(mut self, user_agent: impl Into<String>) -> Self {
+        self.user_agent = Some(user_agent.into());
+        self
+    }
+
+    /// HTTP-прокси для всех запросов
+    pub fn proxy(mut self, url: impl Into<String>) -> Self {
+        self.proxy = Some(url.into());
+        self
+    }
+
+    /// Basic-авторизация на прокси
+    pub fn proxy_basic_auth(mut self, username: impl Into<String>, password: impl Into<String>) -> Self {
+        self.proxy_auth = Some((username.into(), password.into()));
+        self
+    }
+
+    /// Добавить заголовок ко всем запросам
+    pub fn default_header(mut self, name: HeaderName, value: HeaderValue) -> Self {
+        self.default_headers.insert(name, value);
+        self
+    }
+
+    /// Заголовки для всех запросов
+    pub fn default_headers(mut self, headers: HeaderMap) -> Self {
+        self.default_headers.extend(headers);
+        self
+    }
+
+    /// Максимум простаивающих соединений на хост
+    pub fn pool_max_idle_per_host(mut self, max: usize) -> Self {
+        self.pool_max_idle_per_host = Some(max);
+        self
+    }
+
+    /// Время жизни простаивающего соединения
+    pub fn pool_idle_timeout(mut self, timeout: Duration) -> Self {
+        self.pool_idle_timeout = Some(timeout);
+        self
+    }
+
+    /// Предпочтение версии HTTP
+    pub fn http_version(mut self, version: HttpVersionPref) -> Self {
+        self.http_version = version;
+        self
+    }
+
+    /// Реализация TLS
+    #[cfg(any(feature = "default-tls", feature = "rustls-tls"))]
+    pub fn tls_backend(mut self, backend: TlsBackend) -> Self {
+        self.tls.backend = Some(backend);
+        self
+    }
+
+    /// Доверять корневым сертификатам из PEM (допускается несколько в одном блоке)
+    #[cfg(any(feature = "default-tls", feature = "rustls-tls"))]
+    pub fn add_root_certificate_pem(mut self, pem: impl Into<Vec<u8>>) -> Self {
+        self.tls.root_certificates.push(PemSource::Bytes(pem.into()));
+        self
+    }
+
+    /// Доверять корневым сертификатам из PEM-файла; файл читается при `build`
+    #[cfg(any(feature = "default-tls", feature = "rustls-tls"))]
+    pub fn add_root_certificate_file(mut self, path: impl Into<PathBuf>) -> Self {
+        self.tls.root_certificates.push(PemSource::File(path.into()));
+        self
+    }
+
+    /// Использовать встроенные корневые сертификаты (по умолчанию да)
+    #[cfg(any(feature = "default-tls", feature = "rustls-tls"))]
+    pub fn tls_built_in_root_certs(mut self, enable: bool) -> Self {
+        self.tls.disable_built_in_roots = !enable;
+        self
+    }
+
+    /// Предъявлять клиентский сертификат (mTLS)
+    #[cfg(any(feature = "default-tls", feature = "rustls-tls"))]
+    pub fn client_identity(mut self, identity: ClientIdentity) -> Self {
+        self.tls.identity = Some(identity);
+        self
+    }
+
+    /// Закрепить сертификат сервера по SHA-256 отпечатку (hex, можно с `:`).
+    ///
+    /// Можно задать несколько отпечатков для ротации. Проверка цепочки сохраняется.
+    #[cfg(feature = "rustls-tls")]
+    pub fn pin_certificate_sha256(mut self, fingerprint: impl Into<String>) -> Self {
+        self.tls.pins.push(fingerprint.into());
+        self
+    }
+
+    /// Заданы ли настройки, которые применяются при создании `reqwest::Client`
+    fn has_transport_options(&self) -> bool {
+        #[cfg(any(feature = "default-tls", feature = "rustls-tls"))]
+        if !self.tls.is_default() {
+            return true;
+        }
+        self.connect_timeout.is_some()
+            || self.timeout.is_some()
+            || self.user_agent.is_some()
+            || self.proxy.is_some()
+            || self.proxy_auth.is_some()
+            || !self.default_headers.is_empty()
+            || self.pool_max_idle_per_host.is_some()
+            || self.pool_idle_timeout.is_some()
+            || self.http_version != HttpVersionPref::Auto
+    }
+
+    /// Создать `reqwest::Client` по настройкам
+    fn build_http_client(self) -> Result<Client> {
+        let mut builder = Client::builder()
+            .connect_timeout(self.connect_timeout.unwrap_or(DEFAULT_CONNECT_TIMEOUT))
+            .user_agent(self.user_agent.as_deref().unwrap_or(DEFAULT_USER_AGENT))
+            .default_headers(self.default_headers);
+        if let Some(timeout) = self.timeout {
+            builder = builder.timeout(timeout);
+        }
+        if let Some(url) = &self.proxy {
+            let mut proxy = Proxy::all(url.as_str())?;
+            if let Some((username, password)) = &self.proxy_auth {
+                proxy = proxy.basic_auth(username, password);
+            }
+            builder = builder.proxy(proxy);
+        } else if self.proxy_auth.is_some() {
+            return Err(Error::Config("proxy credentials given without a proxy".to_string()));
+        }
+        if let Some(max) = self.pool_max_idle_per_host {
+            builder = builder.pool_max_idle_per_host(max);
+        }
+        if let Some(timeout) = self.pool_idle_timeout {
+            builder = builder.pool_idle_timeout(timeout);
+        }
+        builder = match self.http_version {
+            HttpVersionPref::Auto => builder,
+            HttpVersionPref::Http1Only => builder.http1_only(),
+            HttpVersionPref::Http2PriorKnowledge => builder.http2_prior_knowledge(),
+        };
+        #[cfg(any(feature = "default-tls", feature = "rustls-tls"))]
+        {
+            builder = self.tls.apply(builder, self.http_version)?;
+        }
+        Ok(builder.build()?)
+    }
+
+    /// Создать клиент
+    pub fn build(mut self) -> Result<ZeroGalleryClient> {
+        let base_url = self.base_url.trim_end_matches('/').to_string();
+        if base_url.is_empty() {
+            return Err(Error::Config("base URL is empty".to_string()));
+        }
+        let credentials = std::mem::take(&mut self.credentials);
+        let album_credentials = std::mem::take(&mut self.album_credentials);
+        let read_timeout = self.read_timeout;
+        let retry = std::mem::take(&mut self.retry);
+        let rate_limiter = self.rate_limiter.take();
+        let metrics = self.metrics.take().unwrap_or_default();
+        if self.endpoints.iter().any(|endpoint| endpoint.url.is_empty()) {
+            return Err(Error::Config("endpoint URL is empty".to_string()));
+        }
+        let endpoints = Endpoints::new(
+            std::iter::once(Endpoint::writable(base_url.clone()))
+                .chain(std::mem::take(&mut self.endpoints))
+                .collect(),
+            std::mem::take(&mut self.circuit_breaker),
+        );
+        #[cfg(feature = "tower")]
+        let layers = std::mem::take(&mut self.layers);
+        let transport: Arc<dyn Transport> = match (self.http_client.take(), self.transport.take()) {
+            (Some(_), Some(_)) => {
+                return Err(Error::Config(
+                    "a custom reqwest::Client and a custom transport are mutually exclusive".to_string(),
+                ));
+            }
+            (Some(_), None) | (None, Some(_)) if self.has_transport_options() => {
+                return Err(Error::Config(
+                    "transport options cannot be combined with a custom reqwest::Client or transport".to_string(),
+                ));
+            }
+            (Some(client), None) => Arc::new(ReqwestTransport::new(client)),
+            (None, Some(transport)) => transport,
+            (None, None) => Arc::new(ReqwestTransport::new(self.build_http_client()?)),
+        };
+        #[cfg(feature = "tower")]
+        let transport = layers
+            .into_iter()
+            .rev()
+            .fold(transport, |inner, wrap| wrap(inner));
+
+        Ok(ZeroGalleryClient {
+            transport,
+            base_url,
+            credentials,
+            album_credentials,
+            read_timeout,
+            retry,
+            endpoints,
+            rate_limiter,
+            metrics,
+        })
+    }
+}
+
+// Профили конфигурации
+
+/// Переменная окружения с адресом сервера
+pub const ENV_URL: &str = "ZEROGALLERY_URL";
+/// Переменная окружения с токеном записи
+pub const ENV_UPLOAD_TOKEN: &str = "ZEROGALLERY_UPLOAD_TOKEN";
+/// Переменная окружения с токеном доступа
+pub const ENV_ACCESS_TOKEN: &str = "ZEROGALLERY_ACCESS_TOKEN";
+/// Переменная окружения с мастер-токеном
+pub const ENV_MASTER_TOKEN: &str = "ZEROGALLERY_MASTER_TOKEN";
+/// Переменная окружения с путем к файлу профилей
+pub const ENV_CONFIG_PATH: &str = "ZEROGALLERY_CONFIG";
+
+/// Профиль подключения к серверу.
+///
+/// В файле профилей каждый профиль задается таблицей `[profiles.<имя>]`,
+/// токены альбомов - таблицей `[profiles.<имя>.albums]` вида `12 = "token"`.
+#[derive(Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Profile {
+    /// Адрес сервера
+    pub url: Option<String>,
+    /// Токен записи
+    pub upload_token: Option<String>,
+    /// Токен доступа
+    pub access_token: Option<String>,
+    /// Мастер-токен
+    pub master_token: Option<String>,
+    /// Токены альбомов по идентификатору альбома
+    #[serde(default)]
+    pub albums: HashMap<String, String>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ProfileFile {
+    #[serde(default)]
+    profiles: HashMap<String, Profile>,
+}
+
+impl Profile {
+    /// Путь к файлу профилей: `$ZEROGALLERY_CONFIG`, иначе
+    /// `$XDG_CONFIG_HOME/zerogallery/config.toml` или `~/.config/zerogallery/config.toml`
+    pub fn default_path() -> Option<PathBuf> {
+        if let Some(path) = std::env::var_os(ENV_CONFIG_PATH) {
+            return Some(PathBuf::from(path));
+        }
+        let config_home = std::env::var_os("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            .filter(|path| path.is_absolute())
+            .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+        Some(config_home.join("zerogallery").join("config.toml"))
+    }
+
+    /// Загрузить профиль из файла по умолчанию с учетом переменных окружения
+    pub fn load(name: &str) -> Result<Self> {
+        let path = Self::default_path().filter(|path| path.exists());
+        let profile = match &path {
+            Some(path) => Self::read_profile(path, name)?,
+            None => None,
+        };
+        Self::resolve(name, profile, path.as_deref(), |key| std::env::var(key).ok())
+    }
+
+    /// Загрузить профиль из указанного файла с учетом переменных окружения
+    pub fn load_from(path: impl AsRef<Path>, name: &str) -> Result<Self> {
+        Self::load_from_with(path, name, |key| std::env::var(key).ok())
+    }
+
+    /// Загрузить профиль из указанного файла; переопределения берутся из `lookup`
+    /// вместо окружения процесса, см. [`Profile::apply_env`]
+    pub fn load_from_with<F>(path: impl AsRef<Path>, name: &str, lookup: F) -> Result<Self>
+    where
+        F: Fn(&str) -> Option<String>,
+    {
+        let path = path.as_ref();
+        let profile = Self::read_profile(path, name)?;
+        Self::resolve(name, profile, Some(path), lookup)
+    }
+
+    /// Разобрать профиль из TOML-текста
+    pub fn from_toml(text: &str, name: &str) -> Result<Self> {
+        Self::parse_profile(text, name, "<string>")?
+            .ok_or_else(|| Error::Profile(format!("profile '{}' not found", name)))
+    }
+
+    /// Переопределить значения переменными окружения
+    pub fn apply_env<F>(&mut self, lookup: F)
+    where
+        F: Fn(&str) -> Option<String>,
+    {
+        let lookup = |key: &str| lookup(key).filter(|value| !value.is_empty());
+        if let Some(url) = lookup(ENV_URL) {
+            self.url = Some(url);
+        }
+        if let Some(token) = lookup(ENV_UPLOAD_TOKEN) {
+            self.upload_token = Some(token);
+        }
+        if let Some(token) = lookup(ENV_ACCESS_TOKEN) {
+            self.access_token = Some(token);
+        }
+        if let Some(token) = lookup(ENV_MASTER_TOKEN) {
+            self.master_token = Some(token);
+        }
+    }
+
+    /// Учетные данные профиля
+    pub fn credentials(&self) -> Credentials {
+        Credentials {
+            upload_token: self.upload_token.clone(),
+            access_token: self.access_token.clone(),
+            master_token: self.master_token.clone(),
+        }
+    }
+
+    /// Реестр токенов альбомов профиля
+    pub fn album_credentials(&self) -> Result<AlbumCredentials> {
+        let registry = AlbumCredentials::new();
+        for (album, token) in &self.albums {
+            let album_id = album.trim().parse::<AlbumId>().map_err(|_| {
+                Error::Profile(format!(
+                    "album id '{}' is not a number; use `<album id> = \"<token>\"` entries",
+                    album
+                ))
+            })?;
+            if token.is_empty() {
+                return Err(Error::Profile(format!("empty token for album {}", album_id)));
+            }
+            registry.insert(album_id, token.clone());
+        }
+        Ok(registry)
+    }
+
+    fn read_profile(path: &Path, name: &str) -> Result<Option<Self>> {
+        let text = std::fs::read_to_string(path).map_err(|e| {
+            Error::Profile(format!("cannot read {}: {}", path.display(), e))
+        })?;
+        Self::parse_profile(&text, name, &path.display().to_string())
+    }
+
+    fn parse_profile(text: &str, name: &str, source: &str) -> Result<Option<Self>> {
+        let mut file: ProfileFile = toml::from_str(text)
+            .map_err(|e| Error::Profile(format!("malformed {}: {}", source, e)))?;
+        Ok(file.profiles.remove(name))
+    }
+
+    /// Наложить окружение и проверить, что адрес сервера известен
+    fn resolve<F>(name: &str, profile: Option<Self>, path: Option<&Path>, lookup: F) -> Result<Self>
+    where
+        F: Fn(&str) -> Option<String>,
+    {
+        let found = profile.is_some();
+        let mut profile = profile.unwrap_or_default();
+        profile.apply_env(lookup);
+        if profile.url.as_deref().unwrap_or_default().is_empty() {
+            let source = path
+                .map(|path| path.display().to_string())
+                .unwrap_or_else(|| "no profile file".to_string());
+            return Err(Error::Profile(if found {
+                format!("profile '{}' in {} has no url and {} is not set", name, source, ENV_URL)
+            } else {
+                format!("profile '{}' not found ({}) and {} is not set", name, source, ENV_URL)
+            }));
+        }
+        // Проверить токены альбомов сразу, а не при создании клиента
+        profile.album_credentials()?;
+        Ok(profile)
+    }
+}
+
+impl std::fmt::Debug for Profile {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut albums: Vec<_> = self.albums.keys().collect();
+        albums.sort();
+        f.debug_struct("Profile")
+            .field("url", &self.url)
+            .field("credentials", &self.credentials())
+            .field("albums", &albums)
+            .finish()
+    }
+}
+
+// Повторы
+
+/// Политика повторов запросов.
+///
+/// Идемпотентные запросы (GET и DELETE) повторяются при сетевых сбоях, таймаутах
+/// и ответах 408, 429, 502, 503, 504. Пауза растет экспоненциально со случайным
+/// разбросом, `Retry-After` сервера учитывается. `create_album` и `upload_file_data`
+/// повторяются только с включенной сверкой ([`RetryPolicy::with_safe_reconciliation`]):
+/// перед повтором клиент проверяет по спискам сервера, не выполнилась ли уже
+/// предыдущая попытка. `upload_multiple_files` не повторяется.
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    max_retries: u32,
+    initial_backoff: Duration,
+    max_backoff: Duration,
+    multiplier: f64,
+    max_elapsed: Option<Duration>,
+    safe_reconciliation: bool,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_retries: 3,
+            initial_backoff: Duration::from_millis(200),
+            max_backoff: Duration::from_secs(10),
+            multiplier: 2.0,
+            max_elapsed: Some(Duration::from_secs(60)),
+            safe_reconciliation: false,
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Политика по умолчанию: 3 повтора, пауза от 200 мс до 10 с, не дольше минуты
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Без повторов
+    pub fn disabled() -> Self {
+        Self::default().with_max_retries(0)
+    }
+
+    /// Максимальное число повторов после первой попытки
+    pub fn with_max_retries(mut self, max_retries: u32) -> Self {
+        self.max_retries = max_retries;
+        self
+    }
+
+    /// Начальная и максимальная пауза между попытками
+    pub fn with_backoff(mut self, initial: Duration, max: Duration) -> Self {
+        self.initial_backoff = initial;
+        self.max_backoff = max.max(initial);
+        self
+    }
+
+    /// Множитель паузы после каждой попытки (не меньше 1)
+    pub fn with_multiplier(mut self, multiplier: f64) -> Self {
+        self.multiplier = multiplier.max(1.0);
+        self
+    }
+
+    /// Предельное время от начала первой попытки, после которого повторы прекращаются
+    pub fn with_max_elapsed(mut self, max_elapsed: Option<Duration>) -> Self {
+        self.max_elapsed = max_elapsed;
+        self
+    }
+
+    /// Повторять неидемпотентные вызовы со сверкой результата на сервере
+    pub fn with_safe_reconciliation(mut self, enabled: bool) -> Self {
+        self.safe_reconciliation = enabled;
+        self
+    }
+
+    /// Включена ли сверка для неидемпотентных вызовов
+    pub fn safe_reconciliation(&self) -> bool {
+        self.safe_reconciliation
+    }
+
+    /// Пауза перед повтором номер `retry` (с 1): половина фиксирована, половина случайна
+    pub fn backoff(&self, retry: u32) -> Duration {
+        let exponent = retry.saturating_sub(1).min(63) as i32;
+        let base = self.initial_backoff.as_secs_f64() * self.multiplier.powi(exponent);
+        let capped = base.min(self.max_backoff.as_secs_f64());
+        let jitter = (random_u64() as f64 / u64::MAX as f64) * capped / 2.0;
+        Duration::from_secs_f64(capped / 2.0 + jitter)
+    }
+
+    /// Пауза перед повтором номер `retry` или `None`, если повторять не нужно
+    fn next_delay(
+        &self,
+        retry: u32,
+        started: Instant,
+        outcome: std::result::Result<&ApiResponse, &Error>,
+    ) -> Option<Duration> {
+        if retry > self.max_retries {
+            return None;
+        }
+        let delay = match outcome {
+            Ok(response) if is_transient_status(response.status) => {
+                let backoff = self.backoff(retry);
+                response
+                    .header_str(RETRY_AFTER)
+                    .and_then(|value| value.trim().parse::<u64>().ok())
+                    .map(|secs| Duration::from_secs(secs).min(self.max_backoff).max(backoff))
+                    .unwrap_or(backoff)
+            }
+            Err(error) if error.is_retryable() => self.backoff(retry),
+            _ => return None,
+        };
+        match self.max_elapsed {
+            Some(max_elapsed) if started.elapsed() + delay > max_elapsed => None,
+            _ => Some(delay),
+        }
+    }
+}
+
+/// Статусы, после которых запрос имеет смысл повторить
+fn is_transient_status(status: StatusCode) -> bool {
+    matches!(
+        status,
+        StatusCode::REQUEST_TIMEOUT
+            | StatusCode::TOO_MANY_REQUESTS
+            | StatusCode::BAD_GATEWAY
+            | StatusCode::SERVICE_UNAVAILABLE
+            | StatusCode::GATEWAY_TIMEOUT
+    )
+}
+
+/// Случайное число без внешних зависимостей: каждый `RandomState` получает новые ключи
+fn random_u64() -> u64 {
+    use std::hash::{BuildHasher, Hasher};
+    let mut hasher = std::collections::hash_map::RandomState::new().build_hasher();
+    hasher.write_u128(
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_nanos())
+            .unwrap_or_default(),
+    );
+    hasher.finish()
+}
+
+// Точки подключения
+
+/// Адрес сервера галереи
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Endpoint {
+    url: String,
+    writable: bool,
+}
+
+impl Endpoint {
+    /// Сервер, принимающий чтение и запись
+    pub fn writable(url: impl Into<String>) -> Self {
+        Self {
+            url: url.into().trim_end_matches('/').to_string(),
+            writable: true,
+        }
+    }
+
+    /// Реплика только для чтения
+    pub fn read_only(url: impl Into<String>) -> Self {
+        Self {
+            writable: false,
+            ..Self::writable(url)
+        }
+    }
+
+    /// Базовый адрес
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    /// Принимает ли сервер запись
+    pub fn is_writable(&self) -> bool {
+        self.writable
+    }
+}
+
+/// Настройки автомата отключения (circuit breaker) для точек подключения
+#[derive(Debug, Clone)]
+pub struct CircuitBreakerPolicy {
+    failure_threshold: u32,
+    open_duration: Duration,
+}
+
+impl Default for CircuitBreakerPolicy {
+    fn default() -> Self {
+        Self {
+            failure_threshold: 3,
+            open_duration: Duration::from_secs(30),
+        }
+    }
+}
+
+impl CircuitBreakerPolicy {
+    /// Размыкание после 3 сбоев подряд на 30 секунд
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Число сбоев подряд, после которого точка исключается из маршрутизации
+    pub fn with_failure_threshold(mut self, failures: u32) -> Self {
+        self.failure_threshold = failures.max(1);
+        self
+    }
+
+    /// Время до пробного запроса к разомкнутой точке
+    pub fn with_open_duration(mut self, duration: Duration) -> Self {
+        self.open_duration = duration;
+        self
+    }
+}
+
+/// Состояние автомата отключения точки
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CircuitState {
+    /// Точка исправна
+    Closed,
+    /// Точка исключена до пробного запроса
+    Open,
+    /// Выполняется пробный запрос `get_version`
+    HalfOpen,
+}
+
+/// Состояние точки подключения
+#[derive(Debug, Clone)]
+pub struct EndpointHealth {
+    pub endpoint: Endpoint,
+    pub state: CircuitState,
+    /// Сбоев подряд
+    pub failures: u32,
+}
+
+/// Учет состояния точек
+#[derive(Default)]
+struct BreakerState {
+    failures: u32,
+    open_until: Option<Instant>,
+    probing: bool,
+}
+
+/// Выбор точки для запроса
+enum EndpointPick {
+    /// Точка исправна
+    Ready(usize),
+    /// Разомкнутую точку нужно проверить пробным запросом
+    Probe(usize),
+}
+
+/// Набор точек подключения с автоматами отключения.
+///
+/// Первая точка - основной адрес клиента. Чтение идет на исправные точки в порядке
+/// добавления, предпочитая точки без недавних сбоев, запись - только на доступные
+/// для записи. Клоны разделяют состояние автоматов.
+#[derive(Clone)]
+struct Endpoints {
+    endpoints: Vec<Endpoint>,
+    policy: CircuitBreakerPolicy,
+    states: Arc<std::sync::Mutex<Vec<BreakerState>>>,
+}
+
+impl Endpoints {
+    fn new(endpoints: Vec<Endpoint>, policy: CircuitBreakerPolicy) -> Self {
+        let states = endpoints.iter().map(|_| BreakerState::default()).collect();
+        Self {
+            endpoints,
+            policy,
+            states: Arc::new(std::sync::Mutex::new(states)),
+        }
+    }
+
+    /// Нужна ли маршрутизация: с одной точкой автомат отключения не используется
+    fn is_single(&self) -> bool {
+        self.endpoints.len() == 1
+    }
+
+    fn states(&self) -> std::sync::MutexGuard<'_, Vec<BreakerState>> {
+        self.states.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Выбрать точку для чтения или записи
+    fn pick(&self, write: bool) -> Result<EndpointPick> {
+        let now = Instant::now();
+        let mut states = self.states();
+        let mut best: Option<(usize, u32)> = None;
+        for (index, endpoint) in self.endpoints.iter().enumerate() {
+            if write && !endpoint.writable {
+                continue;
+            }
+            let state = &mut states[index];
+            match state.open_until {
+                None => {
+                    if best.is_none_or(|(_, failures)| state.failures < failures) {
+                        best = Some((index, state.failures));
+                    }
+                    if state.failures == 0 {
+                        break;
+                    }
+                }
+                Some(until) if until <= now && !state.probing && best.is_none_or(|(_, f)| f > 0) => {
+                    state.probing = true;
+                    return Ok(EndpointPick::Probe(index));
+                }
+                Some(_) => {}
+            }
+        }
+        match best {
+            Some((index, _)) => Ok(EndpointPick::Ready(index)),
+            None if write && !self.endpoints.iter().any(|e| e.writable) => {
+                Err(Error::Unavailable("no writable endpoint configured".to_string()))
+            }
+            None => Err(Error::Unavailable(format!(
+                "all {} endpoints are unhealthy",
+                if write { "writable" } else { "readable" }
+            ))),
+        }
+    }
+
+    /// Учесть результат запроса к точке
+    fn record(&self, index: usize, failed: bool) {
+        let mut states = self.states();
+        let state = &mut states[index];
+        if failed {
+            state.failures += 1;
+            if state.failures >= self.policy.failure_threshold {
+                state.open_until = Some(Instant::now() + self.policy.open_duration);
+            }
+        } else {
+            state.failures = 0;
+            state.open_until = None;
+        }
+    }
+
+    /// Учесть результат пробного запроса
+    fn finish_probe(&self, index: usize, healthy: bool) {
+        let mut states = self.states();
+        let state = &mut states[index];
+        state.probing = false;
+        if healthy {
+            state.failures = 0;
+            state.open_until = None;
+        } else {
+            state.open_until = Some(Instant::now() + self.policy.open_duration);
+        }
+    }
+
+    fn health(&self) -> Vec<EndpointHealth> {
+        let states = self.states();
+        self.endpoints
+            .iter()
+            .zip(states.iter())
+            .map(|(endpoint, state)| EndpointHealth {
+                endpoint: endpoint.clone(),
+                state: match state.open_until {
+                    None => CircuitState::Closed,
+                    Some(_) if state.probing => CircuitState::HalfOpen,
+                    Some(_) => CircuitState::Open,
+                },
+                failures: state.failures,
+            })
+            .collect()
+    }
+}
+
+/// Сбой, говорящий о неисправности точки, а не о некорректном запросе
+fn is_endpoint_failure(outcome: &Result<ApiResponse>) -> bool {
+    match outcome {
+        Ok(response) => matches!(
+            response.status,
+            StatusCode::BAD_GATEWAY | StatusCode::SERVICE_UNAVAILABLE | StatusCode::GATEWAY_TIMEOUT
+        ),
+        Err(error) => error.is_retryable(),
+    }
+}
+
+// Ограничение скорости
+
+/// Класс трафика для раздельных бюджетов ограничителя
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TrafficClass {
+    /// Загрузка файлов на сервер
+    Upload,
+    /// Скачивание файлов, превью и видео
+    Download,
+    /// Списки, версия и прочие служебные запросы
+    Listing,
+}
+
+impl TrafficClass {
+    /// Класс операции по ее имени ([`ApiRequest::operation`])
+    pub fn of(operation: &str) -> Self {
+        match operation {
+            op if op.starts_with("upload") => TrafficClass::Upload,
+            op if op.starts_with("download") => TrafficClass::Download,
+            "get_data" | "get_preview" | "save_preview" | "get_video_stream" | "read_media" => TrafficClass::Download,
+            _ => TrafficClass::Listing,
+        }
+    }
+
+    fn index(self) -> usize {
+        match self {
+            TrafficClass::Upload => 0,
+            TrafficClass::Download => 1,
+            TrafficClass::Listing => 2,
+        }
+    }
+}
+
+/// Лимит скорости: запросы и байты в секунду.
+///
+/// Запас (burst) равен секунде трафика, но не меньше одного запроса.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct RateLimit {
+    requests_per_second: Option<f64>,
+    bytes_per_second: Option<u64>,
+}
+
+impl RateLimit {
+    /// Без ограничений
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Запросов в секунду
+    pub fn with_requests_per_second(mut self, requests: f64) -> Self {
+        self.requests_per_second = Some(requests).filter(|r| *r > 0.0);
+        self
+    }
+
+    /// Байт тела запроса и ответа в секунду
+    pub fn with_bytes_per_second(mut self, bytes: u64) -> Self {
+        self.bytes_per_second = Some(bytes).filter(|b| *b > 0);
+        self
+    }
+}
+
+/// Ведро токенов с долгом: резерв сверх остатка откладывает следующих, сохраняя очередность
+struct TokenBucket {
+    rate: f64,
+    capacity: f64,
+    tokens: f64,
+    updated: Instant,
+}
+
+impl TokenBucket {
+    fn new(rate: f64, capacity: f64) -> Self {
+        Self {
+            rate,
+            capacity,
+            tokens: capacity,
+            updated: Instant::now(),
+        }
+    }
+
+    /// Зарезервировать `amount` токенов и вернуть паузу до их готовности
+    fn reserve(&mut self, amount: f64, now: Instant) -> Duration {
+        let elapsed = now.saturating_duration_since(self.updated).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.rate).min(self.capacity);
+        self.updated = now;
+        self.tokens -= amount;
+        if self.tokens >= 0.0 {
+            Duration::ZERO
+        } else {
+            Duration::from_secs_f64(-self.tokens / self.rate)
+        }
+    }
+}
+
+/// Ведра одного бюджета
+#[derive(Default)]
+struct Budget {
+    requests: Option<TokenBucket>,
+    bytes: Option<TokenBucket>,
+}
+
+impl Budget {
+    fn new(limit: RateLimit) -> Self {
+        Self {
+            requests: limit.requests_per_second.map(|rate| TokenBucket::new(rate, rate.max(1.0))),
+            bytes: limit
+                .bytes_per_second
+                .map(|rate| TokenBucket::new(rate as f64, rate as f64)),
+        }
+    }
+}
+
+#[derive(Default)]
+struct RateLimiterState {
+    shared: Budget,
+    classes: [Budget; 3],
+}
+
+/// Ограничитель скорости по алгоритму ведра токенов.
+///
+/// Общий бюджет ([`RateLimiter::with_shared`]) делят все классы трафика, бюджет
+/// класса ([`RateLimiter::with_class`]) действует только на свой класс; запрос
+/// проходит оба. Клоны ограничителя делят бюджет, поэтому один ограничитель можно
+/// отдать нескольким клиентам фоновых задач, не затрагивая интерактивных.
+#[derive(Clone, Default)]
+pub struct RateLimiter {
+    state: Arc<std::sync::Mutex<RateLimiterState>>,
+}
+
+impl std::fmt::Debug for RateLimiter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RateLimiter").finish_non_exhaustive()
+    }
+}
+
+/// Размер порции тела, на которую резервируется бюджет байт
+const RATE_LIMIT_CHUNK: usize = 16 * 1024;
+
+impl RateLimiter {
+    /// Ограничитель без лимитов
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Общий бюджет для всех классов трафика
+    pub fn with_shared(self, limit: RateLimit) -> Self {
+        self.lock().shared = Budget::new(limit);
+        self
+    }
+
+    /// Отдельный бюджет класса трафика
+    pub fn with_class(self, class: TrafficClass, limit: RateLimit) -> Self {
+        self.lock().classes[class.index()] = Budget::new(limit);
+        self
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, RateLimiterState> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Есть ли лимит байт для класса
+    fn limits_bytes(&self, class: TrafficClass) -> bool {
+        let state = self.lock();
+        state.shared.bytes.is_some() || state.classes[class.index()].bytes.is_some()
+    }
+
+    /// Дождаться разрешения на запрос
+    pub async fn acquire_request(&self, class: TrafficClass) {
+        let delay = {
+            let mut state = self.lock();
+            let now = Instant::now();
+            let RateLimiterState { shared, classes } = &mut *state;
+            [&mut shared.requests, &mut classes[class.index()].requests]
+                .into_iter()
+                .flatten()
+                .map(|bucket| bucket.reserve(1.0, now))
+                .max()
+                .unwrap_or_default()
+        };
+        if !delay.is_zero() {
+            tokio::time::sleep(delay).await;
+        }
+    }
+
+    /// Дождаться разрешения на передачу `bytes` байт
+    pub async fn acquire_bytes(&self, class: TrafficClass, bytes: u64) {
+        let delay = {
+            let mut state = self.lock();
+            let now = Instant::now();
+            let RateLimiterState { shared, classes } = &mut *state;
+            [&mut shared.bytes, &mut classes[class.index()].bytes]
+                .into_iter()
+                .flatten()
+                .map(|bucket| bucket.reserve(bytes as f64, now))
+                .max()
+                .unwrap_or_default()
+        };
+        if !delay.is_zero() {
+            tokio::time::sleep(delay).await;
+        }
+    }
+
+    /// Поток, передающий байты порциями в пределах бюджета
+    fn throttle<S, E>(&self, class: TrafficClass, stream: S) -> impl Stream<Item = std::result::Result<Bytes, E>> + Send
+    where
+        S: Stream<Item = std::result::Result<Bytes, E>> + Send,
+        E: Send,
+    {
+        let limiter = self.clone();
+        stream
+            .map(|chunk| match chunk {
+                Ok(bytes) => (0..bytes.len())
+                    .step_by(RATE_LIMIT_CHUNK)
+                    .map(|start| Ok(bytes.slice(start..(start + RATE_LIMIT_CHUNK).min(bytes.len()))))
+                    .collect::<Vec<_>>(),
+                Err(error) => vec![Err(error)],
+            })
+            .flat_map(futures_util::stream::iter)
+            .then(move |chunk| {
+                let limiter = limiter.clone();
+                async move {
+                    if let Ok(bytes) = &chunk {
+                        limiter.acquire_bytes(class, bytes.len() as u64).await;
+                    }
+                    chunk
+                }
+            })
+    }
+
+    /// Ограничить тело запроса
+    async fn throttle_request(&self, class: TrafficClass, body: RequestBody) -> RequestBody {
+        if !self.limits_bytes(class) {
+            return body;
+        }
+        match body {
+            RequestBody::Empty => RequestBody::Empty,
+            RequestBody::Bytes(bytes) => {
+                self.acquire_bytes(class, bytes.len() as u64).await;
+                RequestBody::Bytes(bytes)
+            }
+            RequestBody::Stream { stream, length } => RequestBody::Stream {
+                stream: Box::pin(self.throttle(class, stream)),
+                length,
+            },
+        }
+    }
+
+    /// Ограничить тело ответа
+    fn throttle_response(&self, class: TrafficClass, mut response: ApiResponse) -> ApiResponse {
+        if self.limits_bytes(class) {
+            response.body = Box::pin(self.throttle(class, response.body));
+        }
+        response
+    }
+}
+
+// Метрики
+
+/// Границы корзин гистограммы задержек, секунды
+pub const LATENCY_BUCKETS: [f64; 12] = [0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0];
+
+/// Класс ошибки в метриках
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum ErrorClass {
+    /// 401 и 403
+    Auth,
+    /// 404
+    NotFound,
+    /// Прочие ответы 4xx
+    Client,
+    /// Ответы 5xx
+    Server,
+    /// Таймаут соединения или ответа
+    Timeout,
+    /// Сбой соединения или транспорта
+    Network,
+    /// Нет исправной точки подключения
+    Unavailable,
+    /// Прочие ошибки
+    Other,
+}
+
+impl ErrorClass {
+    /// Имя класса для меток
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ErrorClass::Auth => "auth",
+            ErrorClass::NotFound => "not_found",
+            ErrorClass::Client => "client",
+            ErrorClass::Server => "server",
+            ErrorClass::Timeout => "timeout",
+            ErrorClass::Network => "network",
+            ErrorClass::Unavailable => "unavailable",
+            ErrorClass::Other => "other",
+        }
+    }
+
+    /// Класс ответа с ошибочным статусом
+    fn of_status(status: StatusCode) -> Option<Self> {
+        match status.as_u16() {
+            401 | 403 => Some(ErrorClass::Auth),
+            404 => Some(ErrorClass::NotFound),
+            400..=499 => Some(ErrorClass::Client),
+            500..=599 => Some(ErrorClass::Server),
+            _ => None,
+        }
+    }
+
+    /// Класс ошибки без ответа сервера
+    fn of_error(error: &Error) -> Self {
+        match error {
+            Error::ReadTimeout(_) => ErrorClass::Timeout,
+            Error::Request(e) if e.is_timeout() => ErrorClass::Timeout,
+            Error::Request(_) | Error::Transport(_) | Error::Io(_) => ErrorClass::Network,
+            Error::Unavailable(_) => ErrorClass::Unavailable,
+            _ => ErrorClass::Other,
+        }
+    }
+}
+
+/// Гистограмма с фиксированными корзинами
+#[derive(Debug, Clone, PartialEq)]
+pub struct Histogram {
+    /// Верхние границы корзин
+    pub bounds: Vec<f64>,
+    /// Число наблюдений в каждой корзине (не накопительно); последнее - выше всех границ
+    pub counts: Vec<u64>,
+    pub sum: f64,
+    pub count: u64,
+}
+
+impl Histogram {
+    fn new(bounds: &[f64]) -> Self {
+        Self {
+            bounds: bounds.to_vec(),
+            counts: vec![0; bounds.len() + 1],
+            sum: 0.0,
+            count: 0,
+        }
+    }
+
+    fn observe(&mut self, value: f64) {
+        let bucket = self.bounds.iter().position(|bound| value <= *bound).unwrap_or(self.bounds.len());
+        self.counts[bucket] += 1;
+        self.sum += value;
+        self.count += 1;
+    }
+}
+
+/// Метрики одной операции API
+#[derive(Debug, Clone, PartialEq)]
+pub struct OperationMetrics {
+    /// Запросов всего, включая завершившиеся ошибкой
+    pub requests: u64,
+    /// Ответы по статусам
+    pub statuses: BTreeMap<u16, u64>,
+    /// Ошибки по классам
+    pub errors: BTreeMap<ErrorClass, u64>,
+    pub bytes_uploaded: u64,
+    pub bytes_downloaded: u64,
+    /// Время от отправки до конца тела ответа, секунды
+    pub latency: Histogram,
+}
+
+impl Default for OperationMetrics {
+    fn default() -> Self {
+        Self {
+            requests: 0,
+            statuses: BTreeMap::new(),
+            errors: BTreeMap::new(),
+            bytes_uploaded: 0,
+            bytes_downloaded: 0,
+            latency: Histogram::new(&LATENCY_BUCKETS),
+        }
+    }
+}
+
+/// Снимок метрик клиента по операциям
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MetricsSnapshot {
+    pub operations: BTreeMap<&'static str, OperationMetrics>,
+}
+
+impl MetricsSnapshot {
+    /// Метрики в текстовом формате Prometheus
+    pub fn to_prometheus(&self) -> String {
+        use std::fmt::Write;
+
+        fn family(out: &mut String, name: &str, kind: &str, help: &str) {
+            let _ = writeln!(out, "# HELP {} {}\n# TYPE {} {}", name, help, name, kind);
+        }
+
+        let mut out = String::new();
+        family(&mut out, "zerogallery_requests_total", "counter", "Requests sent by the client.");
+        for (operation, metrics) in &self.operations {
+            for (status, count) in &metrics.statuses {
+                let _ = writeln!(
+                    out,
+                    "zerogallery_requests_total{{operation=\"{}\",status=\"{}\"}} {}",
+                    operation, status, count
+                );
+            }
+            let failed = metrics.requests - metrics.statuses.values().sum::<u64>();
+            if failed > 0 {
+                let _ = writeln!(
+                    out,
+                    "zerogallery_requests_total{{operation=\"{}\",status=\"error\"}} {}",
+                    operation, failed
+                );
+            }
+        }
+
+        family(&mut out, "zerogallery_errors_total", "counter", "Failed requests by error class.");
+        for (operation, metrics) in &self.operations {
+            for (class, count) in &metrics.errors {
+                let _ = writeln!(
+                    out,
+                    "zerogallery_errors_total{{operation=\"{}\",class=\"{}\"}} {}",
+                    operation,
+                    class.as_str(),
+                    count
+                );
+            }
+        }
+
+        family(&mut out, "zerogallery_uploaded_bytes_total", "counter", "Request body bytes sent.");
+        for (operation, metrics) in &self.operations {
+            let _ = writeln!(
+                out,
+                "zerogallery_uploaded_bytes_total{{operation=\"{}\"}} {}",
+                operation, metrics.bytes_uploaded
+            );
+        }
+
+        family(&mut out, "zerogallery_downloaded_bytes_total", "counter", "Response body bytes received.");
+        for (operation, metrics) in &self.operations {
+            let _ = writeln!(
+                out,
+                "zerogallery_downloaded_bytes_total{{operation=\"{}\"}} {}",
+                operation, metrics.bytes_downloaded
+            );
+        }
+
+        family(
+            &mut out,
+            "zerogallery_request_duration_seconds",
+            "histogram",
+            "Request latency until the response body is read.",
+        );
+        for (operation, metrics) in &self.operations {
+            let latency = &metrics.latency;
+            let mut cumulative = 0;
+            for (bound, count) in latency.bounds.iter().zip(&latency.counts) {
+                cumulative += count;
+                let _ = writeln!(
+                    out,
+                    "zerogallery_request_duration_seconds_bucket{{operation=\"{}\",le=\"{}\"}} {}",
+                    operation, bound, cumulative
+                );
+            }
+            let _ = writeln!(
+                out,
+                "zerogallery_request_duration_seconds_bucket{{operation=\"{}\",le=\"+Inf\"}} {}",
+                operation, latency.count
+            );
+            let _ = writeln!(
+                out,
+                "zerogallery_request_duration_seconds_sum{{operation=\"{}\"}} {}",
+                operation, latency.sum
+            );
+            let _ = writeln!(
+                out,
+                "zerogallery_request_duration_seconds_count{{operation=\"{}\"}} {}",
+                operation, latency.count
+            );
+        }
+        out
+    }
+}
+
+/// Метрики клиента. Клоны делят одни счетчики, поэтому один набор можно отдать
+/// нескольким клиентам через [`ZeroGalleryClientBuilder::metrics`].
+#[derive(Clone, Default)]
+pub struct ClientMetrics {
+    operations: Arc<std::sync::Mutex<BTreeMap<&'static str, OperationMetrics>>>,
+}
+
+impl std::fmt::Debug for ClientMetrics {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ClientMetrics").finish_non_exhaustive()
+    }
+}
+
+impl ClientMetrics {
+    /// Пустой набор метрик
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Текущие значения
+    pub fn snapshot(&self) -> MetricsSnapshot {
+        MetricsSnapshot {
+            operations: self.lock().clone(),
+        }
+    }
+
+    /// Текущие значения в текстовом формате Prometheus
+    pub fn to_prometheus(&self) -> String {
+        self.snapshot().to_prometheus()
+    }
+
+    /// Сбросить счетчики
+    pub fn reset(&self) {
+        self.lock().clear();
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, BTreeMap<&'static str, OperationMetrics>> {
+        self.operations.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn update(&self, operation: &'static str, apply: impl FnOnce(&mut OperationMetrics)) {
+        apply(self.lock().entry(operation).or_default());
+    }
+
+    /// Учесть результат запроса; объем ответа и задержка учитываются по окончании тела
+    fn observe(
+        &self,
+        operation: &'static str,
+        uploaded: u64,
+        started: Instant,
+        outcome: Result<ApiResponse>,
+    ) -> Result<ApiResponse> {
+        match outcome {
+            Ok(mut response) => {
+                let status = response.status;
+                self.update(operation, |m| {
+                    m.requests += 1;
+                    *m.statuses.entry(status.as_u16()).or_default() += 1;
+                    if let Some(class) = ErrorClass::of_status(status) {
+                        *m.errors.entry(class).or_default() += 1;
+                    }
+                    m.bytes_uploaded += uploaded;
+                });
+                response.body = Box::pin(MeteredBody {
+                    inner: response.body,
+                    metrics: self.clone(),
+                    operation,
+                    started,
+                    finished: false,
+                });
+                Ok(response)
+            }
+            Err(error) => {
+                let class = ErrorClass::of_error(&error);
+                self.update(operation, |m| {
+                    m.requests += 1;
+                    *m.errors.entry(class).or_default() += 1;
+                    m.latency.observe(started.elapsed().as_secs_f64());
+                });
+                Err(error)
+            }
+        }
+    }
+}
+
+/// Тело ответа, учитывающее полученные байты и время до конца передачи
+struct MeteredBody {
+    inner: ResponseBody,
+    metrics: ClientMetrics,
+    operation: &'static str,
+    started: Instant,
+    finished: bool,
+}
+
+impl MeteredBody {
+    fn finish(&mut self) {
+        if !self.finished {
+            self.finished = true;
+            let latency = self.started.elapsed().as_secs_f64();
+            self.metrics.update(self.operation, |m| m.latency.observe(latency));
+        }
+    }
+}
+
+impl Stream for MeteredBody {
+    type Item = Result<Bytes>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut std::task::Context<'_>) -> std::task::Poll<Option<Self::Item>> {
+        let poll = self.inner.as_mut().poll_next(cx);
+        match &poll {
+            std::task::Poll::Ready(Some(Ok(chunk))) => {
+                let bytes = chunk.len() as u64;
+                self.metrics.update(self.operation, |m| m.bytes_downloaded += bytes);
+            }
+            std::task::Poll::Ready(None) => self.finish(),
+            _ => {}
+        }
+        poll
+    }
+}
+
+impl Drop for MeteredBody {
+    fn drop(&mut self) {
+        self.finish();
+    }
+}
+
+// Трассировка
+
+/// Спаны вызовов API и распространение контекста OpenTelemetry
+#[cfg(feature = "tracing")]
+mod telemetry {
+    use super::{ApiRequest, ApiResponse, Bytes, Result, Stream};
+    use std::pin::Pin;
+    use std::task::{Context, Poll};
+    use std::time::Instant;
+
+    /// Спан запроса `zerogallery.request`; статус, объем и время заполняются по ходу
+    pub(super) fn request_span(request: &ApiRequest) -> tracing::Span {
+        let span = tracing::info_span!(
+            "zerogallery.request",
+            operation = request.operation,
+            http.method = %request.method,
+            url = %request.url,
+            album_id = tracing::field::Empty,
+            data_id = tracing::field::Empty,
+            http.status_code = tracing::field::Empty,
+            request_bytes = request.body.length(),
+            response_bytes = tracing::field::Empty,
+            latency_ms = tracing::field::Empty,
+            error = tracing::field::Empty,
+        );
+        let path = request.url.split_once("/api/").map(|(_, path)| path).unwrap_or_default();
+        let segments: Vec<&str> = path.split(['/', '?']).collect();
+        for pair in segments.windows(2) {
+            if let Ok(id) = pair[1].parse::<i64>() {
+                match pair[0] {
+                    "album" | "upload" => span.record("album_id", id),
+                    "data" | "preview" => span.record("data_id", id),
+                    _ => continue,
+                };
+            }
+        }
+        span
+    }
+
+    /// Добавить заголовки W3C `traceparent` и `tracestate` из контекста спана
+    #[cfg(feature = "opentelemetry")]
+    pub(super) fn inject_context(span: &tracing::Span, request: &mut ApiRequest) {
+        use super::HeaderValue;
+        use opentelemetry::trace::TraceContextExt;
+        use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+        let context = span.context();
+        let otel_span = context.span();
+        let span_context = otel_span.span_context();
+        if !span_context.is_valid() {
+            return;
+        }
+        let traceparent = format!(
+            "00-{}-{}-{:02x}",
+            span_context.trace_id(),
+            span_context.span_id(),
+            span_context.trace_flags().to_u8()
+        );
+        if let Ok(value) = HeaderValue::from_str(&traceparent) {
+            request.headers.insert("traceparent", value);
+        }
+        let tracestate = span_context.trace_state().header();
+        if let Ok(value) = HeaderValue::from_str(&tracestate) {
+            if !tracestate.is_empty() {
+                request.headers.insert("tracestate", value);
+            }
+        }
+    }
+
+    #[cfg(not(feature = "opentelemetry"))]
+    pub(super) fn inject_context(_span: &tracing::Span, _request: &mut ApiRequest) {}
+
+    /// Записать итог запроса; объем ответа и время записываются по окончании тела
+    pub(super) fn finish(span: tracing::Span, started: Instant, outcome: Result<ApiResponse>) -> Result<ApiResponse> {
+        match outcome {
+            Ok(mut response) => {
+                span.record("http.status_code", response.status.as_u16());
+                response.body = Box::pin(TracedBody {
+                    inner: response.body,
+                    span,
+                    started,
+                    bytes: 0,
+                    finished: false,
+                });
+                Ok(response)
+            }
+            Err(error) => {
+                span.record("latency_ms", started.elapsed().as_millis() as u64);
+                span.record("error", tracing::field::display(&error));
+                tracing::warn!(parent: &span, %error, "zerogallery request failed");
+                Err(error)
+            }
+        }
+    }
+
+    /// Тело ответа, считающее переданные байты
+    struct TracedBody {
+        inner: super::ResponseBody,
+        span: tracing::Span,
+        started: Instant,
+        bytes: u64,
+        finished: bool,
+    }
+
+    impl TracedBody {
+        fn finish(&mut self, completed: bool) {
+            if self.finished {
+                return;
+            }
+            self.finished = true;
+            self.span.record("response_bytes", self.bytes);
+            self.span.record("latency_ms", self.started.elapsed().as_millis() as u64);
+            if completed {
+                tracing::debug!(parent: &self.span, bytes = self.bytes, "zerogallery request completed");
+            } else {
+                tracing::debug!(parent: &self.span, bytes = self.bytes, "zerogallery response body dropped");
+            }
+        }
+    }
+
+    impl Stream for TracedBody {
+        type Item = Result<Bytes>;
+
+        fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+            let poll = self.inner.as_mut().poll_next(cx);
+            match &poll {
+                Poll::Ready(Some(Ok(chunk))) => self.bytes += chunk.len() as u64,
+                Poll::Ready(Some(Err(error))) => {
+                    self.span.record("error", tracing::field::display(error));
+                }
+                Poll::Ready(None) => self.finish(true),
+                Poll::Pending => {}
+            }
+            poll
+        }
+    }
+
+    impl Drop for TracedBody {
+        fn drop(&mut self) {
+            self.finish(false);
+        }
+    }
+}
+
+// Транспорт
+
+/// Поток фрагментов тела запроса
+pub type RequestStream = Pin<Box<dyn Stream<Item = std::io::Result<Bytes>> + Send>>;
+
+/// Поток фрагментов тела ответа
+pub type ResponseBody = Pin<Box<dyn Stream<Item = Result<Bytes>> + Send>>;
+
+/// Тело запроса
+pub enum RequestBody {
+    /// Без тела
+    Empty,
+    /// Тело целиком в памяти
+    Bytes(Bytes),
+    /// Потоковое тело; длина известна, если задан `length`
+    Stream {
+        stream: RequestStream,
+        length: Option<u64>,
+    },
+}
+
+impl RequestBody {
+    /// Длина тела, если известна
+    pub fn length(&self) -> Option<u64> {
+        match self {
+            RequestBody::Empty => Some(0),
+            RequestBody::Bytes(bytes) => Some(bytes.len() as u64),
+            RequestBody::Stream { length, .. } => *length,
+        }
+    }
+
+    /// Тело передается потоком (загрузка файлов)
+    pub fn is_stream(&self) -> bool {
+        matches!(self, RequestBody::Stream { .. })
+    }
+}
+
+impl std::fmt::Debug for RequestBody {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RequestBody::Empty => f.write_str("Empty"),
+            RequestBody::Bytes(bytes) => write!(f, "Bytes({} bytes)", bytes.len()),
+            RequestBody::Stream { length, .. } => write!(f, "Stream(length: {:?})", length),
+        }
+    }
+}
+
+/// Описание HTTP-запроса к API
+#[derive(Debug)]
+pub struct ApiRequest {
+    /// Имя операции клиента, например `get_albums`
+    pub operation: &'static str,
+    pub method: Method,
+    /// Полный URL
+    pub url: String,
+    pub headers: HeaderMap,
+    pub body: RequestBody,
+}
+
+impl ApiRequest {
+    /// Запрос без тела
+    pub fn new(operation: &'static str, method: Method, url: impl Into<String>) -> Self {
+        Self {
+            operation,
+            method,
+            url: url.into(),
+            headers: HeaderMap::new(),
+            body: RequestBody::Empty,
+        }
+    }
+
+    /// Добавить заголовки
+    pub fn with_headers(mut self, headers: HeaderMap) -> Self {
+        self.headers.extend(headers);
+        self
+    }
+
+    /// Задать тело
+    pub fn with_body(mut self, body: RequestBody) -> Self {
+        self.body = body;
+        self
+    }
+
+    /// Задать тело в формате JSON
+    pub fn with_json<T: Serialize>(mut self, value: &T) -> Result<Self> {
+        let body = serde_json::to_vec(value)
+            .map_err(|e| Error::Io(std::io::Error::new(std::io::ErrorKind::InvalidInput, e)))?;
+        self.headers
+            .insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+        self.body = RequestBody::Bytes(body.into());
+        Ok(self)
+    }
+}
+
+/// Ответ API с потоковым телом
+pub struct ApiResponse {
+    pub status: StatusCode,
+    pub headers: HeaderMap,
+    pub body: ResponseBody,
+}
+
+impl ApiResponse {
+    /// Ответ с телом в памяти
+    pub fn from_bytes(status: StatusCode, headers: HeaderMap, body: impl Into<Bytes>) -> Self {
+        let body: Bytes = body.into();
+        Self {
+            status,
+            headers,
+            body: Box::pin(futures_util::stream::once(async move { Ok(body) })),
+        }
+    }
+
+    /// Значение заголовка строкой
+    pub fn header_str(&self, name: impl reqwest::header::AsHeaderName) -> Option<&str> {
+        self.headers.get(name).and_then(|value| value.to_str().ok())
+    }
+
+    /// Длина тела из `Content-Length`
+    pub fn content_length(&self) -> Option<u64> {
+        self.header_str(CONTENT_LENGTH)
+            .and_then(|value| value.parse().ok())
+    }
+}
+
+impl std::fmt::Debug for ApiResponse {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ApiResponse")
+            .field("status", &self.status)
+            .field("headers", &self.headers)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Транспорт, которым клиент выполняет HTTP-запросы.
+///
+/// Реализация по умолчанию - [`ReqwestTransport`]. Свой транспорт позволяет
+/// подменить сеть (Unix-сокеты, имитация сбоев, запись запросов).
+pub trait Transport: Send + Sync {
+    /// Выполнить запрос и вернуть ответ, не дочитывая тело
+    fn send(&self, request: ApiRequest) -> BoxFuture<'static, Result<ApiResponse>>;
+}
+
+impl<T: Transport + ?Sized> Transport for Arc<T> {
+    fn send(&self, request: ApiRequest) -> BoxFuture<'static, Result<ApiResponse>> {
+        (**self).send(request)
+    }
+}
+
+impl<T: Transport + ?Sized> Transport for Box<T> {
+    fn send(&self, request: ApiRequest) -> BoxFuture<'static, Result<ApiResponse>> {
+        (**self).send(request)
+    }
+}
+
+/// Транспорт на `reqwest`
+#[derive(Debug, Clone)]
+pub struct ReqwestTransport {
+    client: Client,
+}
+
+impl ReqwestTransport {
+    /// Транспорт поверх готового клиента
+    pub fn new(client: Client) -> Self {
+        Self { client }
+    }
+
+    /// Клиент `reqwest`
+    pub fn client(&self) -> &Client {
+        &self.client
+    }
+}
+
+impl Transport for ReqwestTransport {
+    fn send(&self, request: ApiRequest) -> BoxFuture<'static, Result<ApiResponse>> {
+        let client = self.client.clone();
+        Box::pin(async move {
+            let mut builder = client
+                .request(request.method, &request.url)
+                .headers(request.headers);
+            builder = match request.body {
+                RequestBody::Empty => builder,
+                RequestBody::Bytes(bytes) => builder.body(bytes),
+                RequestBody::Stream { stream, length } => {
+                    if let Some(length) = length {
+                        builder = builder.header(CONTENT_LENGTH, length);
+                    }
+                    builder.body(reqwest::Body::wrap_stream(stream))
+                }
+            };
+            let response = builder.send().await?;
+            Ok(ApiResponse {
+                status: response.status(),
+                headers: response.headers().clone(),
+                body: Box::pin(response.bytes_stream().map(|chunk| chunk.map_err(Error::from))),
+            })
+        })
+    }
+}
+
+/// Ошибка произвольного middleware
+pub type BoxError = Box<dyn std::error::Error + Send + Sync>;
+
+/// Конвейер запросов как `tower::Service`: слои tower оборачивают транспорт клиента
+#[cfg(feature = "tower")]
+pub mod service {
+    use super::{ApiRequest, ApiResponse, BoxError, Error, Result, Transport};
+    use futures_util::future::BoxFuture;
+    use std::sync::{Arc, Mutex};
+    use std::task::{Context, Poll};
+    use tower::{Service, ServiceExt};
+
+    /// Транспорт клиента как `tower::Service`
+    #[derive(Clone)]
+    pub struct TransportService {
+        transport: Arc<dyn Transport>,
+    }
+
+    impl TransportService {
+        /// Сервис поверх транспорта
+        pub fn new(transport: Arc<dyn Transport>) -> Self {
+            Self { transport }
+        }
+    }
+
+    impl Service<ApiRequest> for TransportService {
+        type Response = ApiResponse;
+        type Error = Error;
+        type Future = BoxFuture<'static, Result<ApiResponse>>;
+
+        fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<()>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn call(&mut self, request: ApiRequest) -> Self::Future {
+            self.transport.send(request)
+        }
+    }
+
+    /// `tower::Service` как транспорт клиента.
+    ///
+    /// Каждый запрос выполняется на клоне сервиса, поэтому сервис должен быть `Clone`
+    /// (как большинство слоев tower). Ошибки слоев возвращаются как [`Error::Transport`],
+    /// ошибки клиента проходят без изменений.
+    pub struct ServiceTransport<S> {
+        service: Mutex<S>,
+    }
+
+    impl<S> ServiceTransport<S> {
+        /// Транспорт поверх сервиса
+        pub fn new(service: S) -> Self {
+            Self {
+                service: Mutex::new(service),
+            }
+        }
+    }
+
+    impl<S> Transport for ServiceTransport<S>
+    where
+        S: Service<ApiRequest, Response = ApiResponse> + Clone + Send + 'static,
+        S::Error: Into<BoxError>,
+        S::Future: Send + 'static,
+    {
+        fn send(&self, request: ApiRequest) -> BoxFuture<'static, Result<ApiResponse>> {
+            let service = self
+                .service
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .clone();
+            Box::pin(async move { service.oneshot(request).await.map_err(into_error) })
+        }
+    }
+
+    /// Вернуть ошибку клиента как есть, остальные завернуть в `Error::Transport`
+    fn into_error(error: impl Into<BoxError>) -> Error {
+        match error.into().downcast::<Error>() {
+            Ok(error) => *error,
+            Err(error) => Error::Transport(error),
+        }
+    }
+}
+
+/// Отложенное применение слоя tower к транспорту при сборке клиента
+#[cfg(feature = "tower")]
+type WrapTransport = Box<dyn FnOnce(Arc<dyn Transport>) -> Arc<dyn Transport> + Send>;
+
+/// Форма multipart/form-data, передаваемая потоком
+struct Multipart {
+    boundary: String,
+    parts: Vec<MultipartPart>,
+}
+
+struct MultipartPart {
+    name: &'static str,
+    file_name: String,
+    body: RequestStream,
+    length: Option<u64>,
+}
+
+impl Multipart {
+    fn new() -> Self {
+        static COUNTER: std::sync::atomic::AtomicU64 = std::sync::atomic::AtomicU64::new(0);
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_nanos())
+            .unwrap_or_default();
+        let counter = COUNTER.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+        Self {
+            boundary: format!("zerogallery-{:x}-{:x}", nanos, counter),
+            parts: Vec::new(),
+        }
+    }
+
+    /// Добавить файл из памяти
+    #[cfg(test)]
+    fn file(self, name: &'static str, file_name: &str, data: impl Into<Bytes>) -> Self {
+        let data: Bytes = data.into();
+        let length = data.len() as u64;
+        self.stream(name, file_name, Box::pin(futures_util::stream::once(async move { Ok(data) })), Some(length))
+    }
+
+    /// Добавить файл, передаваемый потоком
+    fn stream(mut self, name: &'static str, file_name: &str, body: RequestStream, length: Option<u64>) -> Self {
+        self.parts.push(MultipartPart {
+            name,
+            file_name: file_name.to_string(),
+            body,
+            length,
+        });
+        self
+    }
+
+    /// Добавить файл из источника; `progress` - учет прогресса и номер файла в нем
+    fn source(
+        self,
+        name: &'static str,
+        source: &UploadSource,
+        progress: Option<(&Arc<UploadTracker>, usize)>,
+    ) -> Result<Self> {
+        let (mut body, length) = source.open()?;
+        if let Some((tracker, index)) = progress {
+            body = tracker.track(index, body);
+        }
+        Ok(self.stream(name, source.file_name(), body, length))
+    }
+
+    /// Заголовок Content-Type формы
+    fn content_type(&self) -> String {
+        format!("multipart/form-data; boundary={}", self.boundary)
+    }
+
+    /// Заголовки части; имена не из ASCII передаются через `filename*` (RFC 5987)
+    fn part_header(&self, part: &MultipartPart) -> String {
+        let plain = part.file_name.chars().all(|c| c.is_ascii_graphic() || c == ' ')
+            && !part.file_name.contains(['"', '\\']);
+        let file_name = if plain {
+            format!("filename=\"{}\"", part.file_name)
+        } else {
+            let fallback: String = part
+                .file_name
+                .chars()
+                .map(|c| if c.is_ascii_alphanumeric() || c == '.' { c } else { '_' })
+                .collect();
+            format!("filename=\"{}\"; filename*=UTF-8''{}", fallback, percent_encode(&part.file_name))
+        };
+        format!(
+            "--{}\r\nContent-Disposition: form-data; name=\"{}\"; {}\r\nContent-Type: application/octet-stream\r\n\r\n",
+            self.boundary, part.name, file_name
+        )
+    }
+
+    /// Превратить форму в потоковое тело; длина известна, если известны длины всех файлов
+    fn into_body(mut self) -> (HeaderValue, RequestBody) {
+        fn chunk(bytes: Bytes) -> RequestStream {
+            Box::pin(futures_util::stream::once(async move { Ok(bytes) }))
+        }
+
+        let content_type = HeaderValue::from_str(&self.content_type())
+            .expect("boundary is a valid header value");
+        let parts = std::mem::take(&mut self.parts);
+        let mut pieces: Vec<RequestStream> = Vec::with_capacity(parts.len() * 3 + 1);
+        let mut length = Some(0u64);
+        for part in parts {
+            let header = Bytes::from(self.part_header(&part));
+            length = length
+                .zip(part.length)
+                .map(|(total, body)| total + header.len() as u64 + body + 2);
+            pieces.push(chunk(header));
+            pieces.push(part.body);
+            pieces.push(chunk(Bytes::from_static(b"\r\n")));
+        }
+        let closing = Bytes::from(format!("--{}--\r\n", self.boundary));
+        length = length.map(|total| total + closing.len() as u64);
+        pieces.push(chunk(closing));
+        (
+            content_type,
+            RequestBody::Stream {
+                stream: Box::pin(futures_util::stream::iter(pieces).flatten()),
+                length,
+            },
+        )
+    }
+}
+
+/// Процентное кодирование для `filename*`
+fn percent_encode(value: &str) -> String {
+    value
+        .bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'.' | b'-' | b'_' | b'~' => (b as char).to_string(),
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+/// Клиент для работы с ZeroGallery API.
+///
+/// Клонирование дешево: клоны разделяют транспорт, токены альбомов, состояние точек
+/// подключения, ограничитель запросов и метрики.
+#[derive(Clone)]
+pub struct ZeroGalleryClient {
+    transport: Arc<dyn Transport>,
+    base_url: String,
+    credentials: Credentials,
+    album_credentials: AlbumCredentials,
+    read_timeout: Option<Duration>,
+    retry: RetryPolicy,
+    endpoints: Endpoints,
+    rate_limiter: Option<RateLimiter>,
+    metrics: ClientMetrics,
+}
+
+impl ZeroGalleryClient {
+    /// Построитель клиента
+    pub fn builder(base_url: impl Into<String>) -> ZeroGalleryClientBuilder {
+        ZeroGalleryClientBuilder::new(base_url)
+    }
+
+    /// Создать новый клиент.
+    ///
+    /// Паникует, если HTTP-клиент не удалось создать; [`ZeroGalleryClient::builder`]
+    /// возвращает ошибку вместо паники.
+    pub fn new(base_url: impl Into<String>) -> Self {
+        Self::builder(base_url).build().expect("Failed to create HTTP client")
+    }
+
+    /// Создать клиент по именованному профилю, см. [`Profile::load`]
+    pub fn from_profile(name: &str) -> Result<Self> {
+        ZeroGalleryClientBuilder::from_profile(&Profile::load(name)?)?.build()
+    }
+    
+    /// Создать клиент с токеном доступа.
+    ///
+    /// Паникует, если HTTP-клиент не удалось создать; [`ZeroGalleryClient::builder`]
+    /// возвращает ошибку вместо паники.
+    pub fn with_token(base_url: impl Into<String>, access_token: Option<String>) -> Self {
+        Self::builder(base_url)
+            .credentials(Credentials {
+                access_token,
+                ..Credentials::default()
+            })
+            .build()
+            .expect("Failed to create HTTP client")
+    }
+
+    /// Создать клиент с учетными данными.
+    ///
+    /// Паникует, если HTTP-клиент не удалось создать; [`ZeroGalleryClient::builder`]
+    /// возвращает ошибку вместо паники.
+    pub fn with_credentials(base_url: impl Into<String>, credentials: Credentials) -> Self {
+        Self::builder(base_url)
+            .credentials(credentials)
+            .build()
+            .expect("Failed to create HTTP client")
+    }
+    
+    /// Установить токен доступа
+    pub fn set_access_token(&mut self, token: Option<String>) {
+        self.credentials.access_token = token;
+    }
+
+    /// Установить учетные данные
+    pub fn set_credentials(&mut self, credentials: Credentials) {
+        self.credentials = credentials;
+    }
+
+    /// Текущие учетные данные
+    pub fn credentials(&self) -> &Credentials {
+        &self.credentials
+    }
+
+    /// Политика повторов
+    pub fn retry_policy(&self) -> &RetryPolicy {
+        &self.retry
+    }
+
+    /// Установить политику повторов
+    pub fn set_retry_policy(&mut self, policy: RetryPolicy) {
+        self.retry = policy;
+    }
+
+    /// Метрики запросов клиента
+    pub fn metrics(&self) -> &ClientMetrics {
+        &self.metrics
+    }
+
+    /// Ограничитель скорости клиента
+    pub fn rate_limiter(&self) -> Option<&RateLimiter> {
+        self.rate_limiter.as_ref()
+    }
+
+    /// Состояние точек подключения; основной адрес первый
+    pub fn endpoint_health(&self) -> Vec<EndpointHealth> {
+        self.endpoints.health()
+    }
+
+    /// Реестр токенов альбомов
+    pub fn album_credentials(&self) -> &AlbumCredentials {
+        &self.album_credentials
+    }
+
+    /// Использовать реестр токенов альбомов (например, общий для нескольких клиентов)
+    pub fn set_album_credentials(&mut self, album_credentials: AlbumCredentials) {
+        self.album_credentials = album_credentials;
+    }
+
+    /// Токен доступа для альбома: из реестра, иначе общий
+    fn access_token_for(&self, album_id: Option<AlbumId>) -> Option<String> {
+        album_id
+            .and_then(|id| self.album_credentials.token(id))
+            .or_else(|| self.credentials.access_token.clone())
+    }
+    
+    /// Создать заголовки с токенами для запроса к альбому (или без альбома)
+    fn create_headers(&self, scope: AuthScope, album_id: Option<AlbumId>) -> Result<HeaderMap> {
+        let credentials = &self.credentials;
+        let (upload, access) = match scope {
+            AuthScope::Public => (None, None),
+            AuthScope::Read => (
+                credentials.master_token.as_deref(),
+                self.access_token_for(album_id),
+            ),
+            AuthScope::Create => (credentials.upload_header_token(), None),
+            AuthScope::Write => (
+                credentials.upload_header_token(),
+                self.access_token_for(album_id),
+            ),
+        };
+
+        let mut headers = HeaderMap::new();
+        if let Some(token) = upload {
+            headers.insert(UPLOAD_TOKEN_HEADER, HeaderValue::from_str(token)?);
+        }
+        if let Some(token) = access {
+            headers.insert(ACCESS_TOKEN_HEADER, HeaderValue::from_str(&token)?);
+        }
+        Ok(headers)
+    }
+
+    /// Создать заголовки для запроса к записи с токеном альбома-владельца
+    fn data_headers(&self, scope: AuthScope, data_id: DataId) -> Result<HeaderMap> {
+        self.create_headers(scope, self.album_credentials.album_of(data_id))
+    }
+    
+    /// Конвейер запросов клиента (транспорт со слоями) как `tower::Service`
+    #[cfg(feature = "tower")]
+    pub fn service(&self) -> service::TransportService {
+        service::TransportService::new(self.transport.clone())
+    }
+
+    /// Создать запрос к API по пути вида `/api/...`
+    fn request(&self, operation: &'static str, method: Method, path: &str, headers: HeaderMap) -> ApiRequest {
+        ApiRequest::new(operation, method, format!("{}{}", self.base_url, path)).with_headers(headers)
+    }
+
+    /// Выполнить запрос с учетом в метриках
+    async fn execute(&self, request: ApiRequest) -> Result<Exchange> {
+        let operation = request.operation;
+        let method = request.method.clone();
+        let url = request.url.clone();
+        let uploaded = request.body.length().unwrap_or(0);
+        let started = Instant::now();
+        let outcome = self.traced(request).await;
+        let response = self.metrics.observe(operation, uploaded, started, outcome)?;
+        Ok(Exchange {
+            operation,
+            method,
+            url,
+            response,
+        })
+    }
+
+    /// Выполнить запрос в спане `zerogallery.request`
+    #[cfg(feature = "tracing")]
+    async fn traced(&self, mut request: ApiRequest) -> Result<ApiResponse> {
+        use tracing::Instrument;
+
+        let span = telemetry::request_span(&request);
+        telemetry::inject_context(&span, &mut request);
+        let started = Instant::now();
+        let outcome = self.limit(request).instrument(span.clone()).await;
+        telemetry::finish(span, started, outcome)
+    }
+
+    #[cfg(not(feature = "tracing"))]
+    async fn traced(&self, request: ApiRequest) -> Result<ApiResponse> {
+        self.limit(request).await
+    }
+
+    /// Выполнить запрос в пределах бюджета ограничителя скорости
+    async fn limit(&self, mut request: ApiRequest) -> Result<ApiResponse> {
+        let limiter = match &self.rate_limiter {
+            Some(limiter) => limiter,
+            None => return self.route(request).await,
+        };
+        let class = TrafficClass::of(request.operation);
+        limiter.acquire_request(class).await;
+        request.body = limiter
+            .throttle_request(class, std::mem::replace(&mut request.body, RequestBody::Empty))
+            .await;
+        let response = self.route(request).await?;
+        Ok(limiter.throttle_response(class, response))
+    }
+
+    /// Выполнить запрос на исправной точке подключения.
+    ///
+    /// GET идет на любую исправную точку, остальные методы - только на доступные для
+    /// записи. Разомкнутая точка по истечении паузы проверяется через `get_version`.
+    async fn route(&self, mut request: ApiRequest) -> Result<ApiResponse> {
+        if self.endpoints.is_single() {
+            return self.send(request).await;
+        }
+        let write = request.method != Method::GET;
+        let index = loop {
+            match self.endpoints.pick(write)? {
+                EndpointPick::Ready(index) => break index,
+                EndpointPick::Probe(index) => {
+                    let healthy = self.probe(self.endpoints.endpoints[index].url()).await;
+                    self.endpoints.finish_probe(index, healthy);
+                    if healthy {
+                        break index;
+                    }
+                }
+            }
+        };
+        if let Some(path) = request.url.strip_prefix(self.base_url.as_str()) {
+            request.url = format!("{}{}", self.endpoints.endpoints[index].url(), path);
+        }
+        let outcome = self.send(request).await;
+        self.endpoints.record(index, is_endpoint_failure(&outcome));
+        outcome
+    }
+
+    /// Пробный запрос `get_version` к точке подключения
+    async fn probe(&self, endpoint_url: &str) -> bool {
+        let request = ApiRequest::new("get_version", Method::GET, format!("{}/api/version", endpoint_url));
+        matches!(self.send(request).await, Ok(response) if response.status == StatusCode::OK)
+    }
+
+    /// Отправить запрос через транспорт.
+    ///
+    /// `read_timeout` ограничивает ожидание заголовков ответа, кроме запросов
+    /// с потоковым телом: время передачи загружаемого файла не ограничивается.
+    async fn send(&self, request: ApiRequest) -> Result<ApiResponse> {
+        let streaming = request.body.is_stream();
+        let response = self.transport.send(request);
+        match self.read_timeout {
+            Some(timeout) if !streaming => tokio::time::timeout(timeout, response)
+                .await
+                .map_err(|_| Error::ReadTimeout(timeout))?,
+            _ => response.await,
+        }
+    }
+
+    /// Выполнить идемпотентный запрос с повторами по политике клиента.
+    ///
+    /// `make_request` создает запрос заново для каждой попытки. Повторяется только
+    /// получение ответа: ответ с временным статусом после последней попытки
+    /// возвращается как есть.
+    async fn execute_idempotent<F>(&self, make_request: F) -> Result<Exchange>
+    where
+        F: Fn() -> Result<ApiRequest>,
+    {
+        let started = Instant::now();
+        let mut retry = 0;
+        loop {
+            let outcome = self.execute(make_request()?).await;
+            retry += 1;
+            match self.retry.next_delay(retry, started, outcome.as_ref().map(|e| &e.response)) {
+                Some(delay) => tokio::time::sleep(delay).await,
+                None => return outcome,
+            }
+        }
+    }
+
+    /// Выполнить неидемпотентный запрос со сверкой.
+    ///
+    /// Без [`RetryPolicy::with_safe_reconciliation`] запрос выполняется один раз. Иначе
+    /// после временного сбоя и паузы вызывается `reconcile`: если он находит
+    /// результат предыдущей попытки на сервере, запрос не повторяется.
+    async fn execute_reconciled<T, F, R, Fut>(&self, make_request: F, reconcile: R) -> Result<Reconciled<T>>
+    where
+        F: Fn() -> Result<ApiRequest>,
+        R: Fn() -> Fut,
+        Fut: std::future::Future<Output = Result<Option<T>>>,
+    {
+        if !self.retry.safe_reconciliation {
+            return self.execute(make_request()?).await.map(Reconciled::Response);
+        }
+        let started = Instant::now();
+        let mut retry = 0;
+        loop {
+            let outcome = self.execute(make_request()?).await;
+            retry += 1;
+            match self.retry.next_delay(retry, started, outcome.as_ref().map(|e| &e.response)) {
+                Some(delay) => {
+                    drop(outcome);
+                    tokio::time::sleep(delay).await;
+                    if let Some(found) = reconcile().await? {
+                        return Ok(Reconciled::Found(found));
+                    }
+                }
+                None => return outcome.map(Reconciled::Response),
+            }
+        }
+    }
+
+    /// Следующий фрагмент тела ответа с учетом `read_timeout`
+    async fn next_chunk(&self, body: &mut ResponseBody) -> Result<Option<Bytes>> {
+        let chunk = match self.read_timeout {
+            Some(timeout) => tokio::time::timeout(timeout, body.next())
+                .await
+                .map_err(|_| Error::ReadTimeout(timeout))?,
+            None => body.next().await,
+        };
+        chunk.transpose()
+    }
+
+    /// Прочитать тело ответа целиком
+    async fn read_body(&self, response: Exchange) -> Result<Vec<u8>> {
+        let mut body = Vec::with_capacity(response.content_length().unwrap_or(0) as usize);
+        let mut stream = response.response.body;
+        while let Some(chunk) = self.next_chunk(&mut stream).await? {
+            body.extend_from_slice(&chunk);
+        }
+        Ok(body)
+    }
+
+    /// Тело ответа потоком вместе с заголовками
+    fn data_stream(&self, response: Exchange) -> DataStream {
+        let ApiResponse { status, headers, body } = response.response;
+        DataStream {
+            status,
+            headers,
+            body: self.timed_body(body),
+        }
+    }
+
+    /// Тело с таймаутом чтения на каждую порцию; после таймаута поток завершается
+    fn timed_body(&self, body: ResponseBody) -> ResponseBody {
+        let Some(timeout) = self.read_timeout else {
+            return body;
+        };
+        Box::pin(futures_util::stream::unfold(Some(body), move |body| async move {
+            let mut body = body?;
+            match tokio::time::timeout(timeout, body.next()).await {
+                Ok(chunk) => chunk.map(|chunk| (chunk, Some(body))),
+                Err(_) => Some((Err(Error::ReadTimeout(timeout)), None)),
+            }
+        }))
+    }
+
+    /// Прочитать тело ответа как текст
+    async fn read_text(&self, response: Exchange) -> Result<String> {
+        let body = self.read_body(response).await?;
+        Ok(String::from_utf8_lossy(&body).into_owned())
+    }
+    
+    /// Ошибка по ответу с неуспешным статусом; тело ответа сохраняется в контексте
+    async fn error_for(&self, response: Exchange) -> Error {
+        let (operation, method, url, status) =
+            (response.operation, response.method.clone(), response.url.clone(), response.status);
+        let body = self.read_body(response).await.unwrap_or_default();
+        Error::from_response(ResponseContext::new(operation, method, url, status, &body))
+    }
+
+    /// Обработать ответ API
+    async fn handle_response<T: for<'de> Deserialize<'de>>(
+        &self,
+        response: Exchange,
+    ) -> Result<T> {
+        match response.status {
+            StatusCode::OK => {
+                let (operation, method, url, status) =
+                    (response.operation, response.method.clone(), response.url.clone(), response.status);
+                let body = self.read_body(response).await?;
+                serde_json::from_slice(&body).map_err(|source| Error::InvalidResponse {
+                    context: Box::new(ResponseContext::new(operation, method, url, status, &body)),
+                    source,
+                })
+            }
+            _ => Err(self.error_for(response).await),
+        }
+    }
+    
+    /// Получить версию API
+    pub async fn get_version(&self) -> Result<String> {
+        let response = self
+            .execute_idempotent(|| {
+                Ok(self.request(
+                    "get_version",
+                    Method::GET,
+                    "/api/version",
+                    self.create_headers(AuthScope::Public, None)?,
+                ))
+            })
+            .await?;
+            
+        match response.status {
+            StatusCode::OK => self.read_text(response).await,
+            _ => Err(self.error_for(response).await),
+        }
+    }
+    
+    /// Получить список альбомов
+    pub async fn get_albums(&self) -> Result<Vec<AlbumInfo>> {
+        let response = self
+            .execute_idempotent(|| {
+                Ok(self.request(
+                    "get_albums",
+                    Method::GET,
+                    "/api/albums",
+                    self.create_headers(AuthScope::Read, None)?,
+                ))
+            })
+            .await?;
+            
+        self.handle_response(response).await
+    }
+    
+    /// Создать новый альбом
+    ///
+    /// Повторяется только со сверкой: новый альбом с тем же именем считается
+    /// созданным предыдущей попыткой.
+    pub async fn create_album(&self, info: CreateAlbumInfo) -> Result<AlbumInfo> {
+        let known: Vec<AlbumId> = if self.retry.safe_reconciliation {
+            self.get_albums().await?.iter().map(|album| album.id).collect()
+        } else {
+            Vec::new()
+        };
+        let reconciled = self
+            .execute_reconciled(
+                || {
+                    self.request(
+                        "create_album",
+                        Method::POST,
+                        "/api/album",
+                        self.create_headers(AuthScope::Create, None)?,
+                    )
+                    .with_json(&info)
+                },
+                || async {
+                    Ok(self
+                        .get_albums()
+                        .await?
+                        .into_iter()
+                        .filter(|album| album.name == info.name && !known.contains(&album.id))
+                        .max_by_key(|album| album.id))
+                },
+            )
+            .await?;
+            
+        let album: AlbumInfo = match reconciled {
+            Reconciled::Response(response) => self.handle_response(response).await?,
+            Reconciled::Found(album) => album,
+        };
+        if !info.token.is_empty() {
+            self.album_credentials.insert(album.id, info.token);
+        }
+        Ok(album)
+    }
+    
+    /// Удалить альбом
+    pub async fn delete_album(&self, album_id: AlbumId) -> Result<()> {
+        let response = self
+            .execute_idempotent(|| {
+                Ok(self.request(
+                    "delete_album",
+                    Method::DELETE,
+                    &format!("/api/album/{}", album_id),
+                    self.create_headers(AuthScope::Write, Some(album_id))?,
+                ))
+            })
+            .await?;
+            
+        match response.status {
+            StatusCode::OK => Ok(()),
+            _ => Err(self.error_for(response).await),
+        }
+    }
+    
+    /// Получить данные без альбомов
+    pub async fn get_data_without_albums(&self) -> Result<Vec<DataInfo>> {
+        let response = self
+            .execute_idempotent(|| {
+                Ok(self.request(
+                    "get_data_without_albums",
+                    Method::GET,
+                    "/api/data",
+                    self.create_headers(AuthScope::Read, None)?,
+                ))
+            })
+            .await?;
+            
+        let items: Vec<DataInfo> = self.handle_response(response).await?;
+        self.album_credentials.remember_all(&items);
+        Ok(items)
+    }
+    
+    /// Получить данные альбома
+    pub async fn get_album_data(&self, album_id: AlbumId) -> Result<Vec<DataInfo>> {
+        let response = self
+            .execute_idempotent(|| {
+                Ok(self.request(
+                    "get_album_data",
+                    Method::GET,
+                    &format!("/api/album/{}/data", album_id),
+                    self.create_headers(AuthScope::Read, Some(album_id))?,
+                ))
+            })
+            .await?;
+            
+        let items: Vec<DataInfo> = self.handle_response(response).await?;
+        self.album_credentials.remember_all(&items);
+        Ok(items)
+    }
+    
+    /// Загрузить файл
+    pub async fn upload_file<P: AsRef<Path>>(
+        &self,
+        file_path: P,
+        target: impl Into<UploadTarget>,
+    ) -> Result<DataId> {
+        self.upload_file_with(file_path, target, TransferOptions::new()).await
+    }
+
+    /// Загрузить файл с возможностью отмены
+    pub async fn upload_file_with<P: AsRef<Path>>(
+        &self,
+        file_path: P,
+        target: impl Into<UploadTarget>,
+        options: TransferOptions,
+    ) -> Result<DataId> {
+        let source = UploadSource::from_path(file_path)?;
+        let target = target.into();
+        options.run(self.upload_one("upload_file", &source, target, &options)).await
+    }
+
+    /// Загрузить файл из источника потоком, не читая его в память целиком
+    pub async fn upload_source(
+        &self,
+        source: UploadSource,
+        target: impl Into<UploadTarget>,
+        options: TransferOptions,
+    ) -> Result<DataId> {
+        let target = target.into();
+        options.run(self.upload_one("upload_source", &source, target, &options)).await
+    }
+
+    /// Загрузить несколько файлов из источников одной формой
+    pub async fn upload_sources(
+        &self,
+        sources: Vec<UploadSource>,
+        target: impl Into<UploadTarget>,
+        options: TransferOptions,
+    ) -> Result<Vec<DataId>> {
+        let target = target.into();
+        let tracker = UploadTracker::requested(&options, &sources);
+        options
+            .run(self.upload_form("upload_sources", &sources, target, tracker.as_ref().map(|tracker| (tracker, 0))))
+            .await
+    }
+
+    /// Загрузить файлы несколькими формами в пределах бюджета планировщика.
+    ///
+    /// Идентификаторы возвращаются в порядке источников. Форму, отклоненную сервером
+    /// как слишком большую (413), планировщик делит пополам и отправляет заново, если
+    /// ее источники можно повторить. При ошибке уже загруженные формы остаются на сервере.
+    pub async fn upload_batched(
+        &self,
+        sources: Vec<UploadSource>,
+        target: impl Into<UploadTarget>,
+        planner: &UploadBatchPlanner,
+        options: TransferOptions,
+    ) -> Result<Vec<DataId>> {
+        let target = target.into();
+        let tracker = UploadTracker::requested(&options, &sources);
+        options
+            .run(async {
+                let mut ids = Vec::with_capacity(sources.len());
+                let mut batches: std::collections::VecDeque<_> = planner.plan(&sources).into();
+                while let Some(batch) = batches.pop_front() {
+                    let batch_sources = &sources[batch.clone()];
+                    let progress = tracker.as_ref().map(|tracker| (tracker, batch.start));
+                    match self.upload_form("upload_batched", batch_sources, target, progress).await {
+                        Ok(batch_ids) => ids.extend(batch_ids),
+                        Err(error)
+                            if error.status() == Some(StatusCode::PAYLOAD_TOO_LARGE)
+                                && batch.len() > 1
+                                && batch_sources.iter().all(UploadSource::is_replayable) =>
+                        {
+                            let middle = batch.start + batch.len() / 2;
+                            batches.push_front(middle..batch.end);
+                            batches.push_front(batch.start..middle);
+                        }
+                        Err(error) => return Err(error),
+                    }
+                }
+                Ok(ids)
+            })
+            .await
+    }
+
+    /// Путь загрузки в цель
+    fn upload_path(target: UploadTarget) -> String {
+        match target {
+            UploadTarget::Public => "/api/upload".to_string(),
+            UploadTarget::Album(album_id) => format!("/api/upload/{}", album_id),
+        }
+    }
+
+    /// Содержимое цели загрузки: альбом или данные без альбома
+    async fn upload_target_data(&self, target: UploadTarget) -> Result<Vec<DataInfo>> {
+        match target {
+            UploadTarget::Public => self.get_data_without_albums().await,
+            UploadTarget::Album(album_id) => self.get_album_data(album_id).await,
+        }
+    }
+
+    /// Запрос загрузки формы
+    fn upload_request(&self, operation: &'static str, target: UploadTarget, form: Multipart) -> Result<ApiRequest> {
+        let mut headers = self.create_headers(AuthScope::Write, target.album_id())?;
+        let (content_type, body) = form.into_body();
+        headers.insert(CONTENT_TYPE, content_type);
+        Ok(self
+            .request(operation, Method::POST, &Self::upload_path(target), headers)
+            .with_body(body))
+    }
+    
+    /// Загрузить файл из данных
+    pub async fn upload_file_data(
+        &self,
+        data: &[u8],
+        filename: &str,
+        target: impl Into<UploadTarget>,
+    ) -> Result<DataId> {
+        self.upload_file_data_with(data, filename, target, TransferOptions::new()).await
+    }
+
+    /// Загрузить файл из данных с возможностью отмены
+    pub async fn upload_file_data_with(
+        &self,
+        data: &[u8],
+        filename: &str,
+        target: impl Into<UploadTarget>,
+        options: TransferOptions,
+    ) -> Result<DataId> {
+        let source = UploadSource::from_bytes(filename, Bytes::copy_from_slice(data));
+        let target = target.into();
+        options.run(self.upload_one("upload_file_data", &source, target, &options)).await
+    }
+
+    /// Загрузить один файл
+    async fn upload_one(
+        &self,
+        operation: &'static str,
+        source: &UploadSource,
+        target: UploadTarget,
+        options: &TransferOptions,
+    ) -> Result<DataId> {
+        let tracker = UploadTracker::requested(options, [source]);
+        self.upload_tracked(operation, source, target, tracker.as_ref().map(|tracker| (tracker, 0)))
+            .await
+    }
+
+    /// Загрузить один файл с учетом прогресса под номером в учете
+    ///
+    /// Повторяется только со сверкой и только для повторяемого источника: новый
+    /// файл с тем же именем в целевом альбоме считается загруженным предыдущей попыткой.
+    async fn upload_tracked(
+        &self,
+        operation: &'static str,
+        source: &UploadSource,
+        target: UploadTarget,
+        progress: Option<(&Arc<UploadTracker>, usize)>,
+    ) -> Result<DataId> {
+        let make_request = || {
+            let form = Multipart::new().source("file", source, progress)?;
+            self.upload_request(operation, target, form)
+        };
+        let response = if source.is_replayable() {
+            let filename = source.file_name();
+            let known: Vec<DataId> = if self.retry.safe_reconciliation {
+                self.upload_target_data(target).await?.iter().map(|item| item.id).collect()
+            } else {
+                Vec::new()
+            };
+            self.execute_reconciled(make_request, || async {
+                Ok(self
+                    .upload_target_data(target)
+                    .await?
+                    .into_iter()
+                    .filter(|item| item.name == filename && !known.contains(&item.id))
+                    .map(|item| item.id)
+                    .max())
+            })
+            .await?
+        } else {
+            Reconciled::Response(self.execute(make_request()?).await?)
+        };
+            
+        let id: DataId = match response {
+            Reconciled::Response(response) => self.handle_response(response).await?,
+            Reconciled::Found(id) => id,
+        };
+        if let Some(album_id) = target.album_id() {
+            self.album_credentials.remember_data(id, album_id);
+        }
+        Ok(id)
+    }
+    
+    /// Загрузить несколько файлов
+    pub async fn upload_multiple_files<P: AsRef<Path>>(
+        &self,
+        file_paths: &[P],
+        target: impl Into<UploadTarget>,
+    ) -> Result<Vec<DataId>> {
+        self.upload_multiple_files_with(file_paths, target, TransferOptions::new()).await
+    }
+
+    /// Загрузить несколько файлов с возможностью отмены
+    pub async fn upload_multiple_files_with<P: AsRef<Path>>(
+        &self,
+        file_paths: &[P],
+        target: impl Into<UploadTarget>,
+        options: TransferOptions,
+    ) -> Result<Vec<DataId>> {
+        let sources = file_paths
+            .iter()
+            .map(UploadSource::from_path)
+            .collect::<Result<Vec<_>>>()?;
+        let target = target.into();
+        let tracker = UploadTracker::requested(&options, &sources);
+        options
+            .run(self.upload_form(
+                "upload_multiple_files",
+                &sources,
+                target,
+                tracker.as_ref().map(|tracker| (tracker, 0)),
+            ))
+            .await
+    }
+
+    /// Загрузить файлы одной формой; `progress` - учет и номер первого файла в нем
+    async fn upload_form(
+        &self,
+        operation: &'static str,
+        sources: &[UploadSource],
+        target: UploadTarget,
+        progress: Option<(&Arc<UploadTracker>, usize)>,
+    ) -> Result<Vec<DataId>> {
+        let mut form = Multipart::new();
+        for (index, source) in sources.iter().enumerate() {
+            form = form.source("files", source, progress.map(|(tracker, first)| (tracker, first + index)))?;
+        }
+        
+        let request = self.upload_request(operation, target, form)?;
+        let response = self.execute(request).await?;
+        if response.status != StatusCode::OK {
+            return Err(self.error_for(response).await);
+        }
+            
+        let (operation, method, url, status) =
+            (response.operation, response.method.clone(), response.url.clone(), response.status);
+        let body = self.read_body(response).await?;
+        let ids = serde_json::from_slice::<UploadResponse>(&body)
+            .map(UploadResponse::into_ids)
+            .and_then(|ids| {
+                if ids.len() == sources.len() {
+                    Ok(ids)
+                } else {
+                    Err(serde::de::Error::invalid_length(ids.len(), &"one id per uploaded file"))
+                }
+            })
+            .map_err(|source| Error::InvalidResponse {
+                context: Box::new(ResponseContext::new(operation, method, url, status, &body)),
+                source,
+            })?;
+        if let Some(album_id) = target.album_id() {
+            for id in &ids {
+                self.album_credentials.remember_data(*id, album_id);
+            }
+        }
+        Ok(ids)
     }
     
     /// Получить превью
-    pub async fn get_preview(&self, data_id: i64) -> Result<Vec<u8>> {
-        let url = format!("{}/api/preview/{}", self.base_url, data_id);
-        let response = self.client
-            .get(&url)
-            .headers(self.create_headers())
-            .send()
+    pub async fn get_preview(&self, data_id: DataId) -> Result<Vec<u8>> {
+        self.stream_preview(data_id).await?.read_all().await
+    }
+
+    /// Получить превью потоком
+    pub async fn stream_preview(&self, data_id: DataId) -> Result<DataStream> {
+        let response = self
+            .execute_idempotent(|| {
+                Ok(self.request(
+                    "get_preview",
+                    Method::GET,
+                    &format!("/api/preview/{}", data_id),
+                    self.data_headers(AuthScope::Read, data_id)?,
+                ))
+            })
             .await?;
             
-        match response.status() {
-            StatusCode::OK => Ok(response.bytes().await?.to_vec()),
-            StatusCode::UNAUTHORIZED => Err(Error::Unauthorized),
-            StatusCode::NOT_FOUND => Err(Error::NotFound(format!("Preview for data {} not found", data_id))),
-            status => {
-                let message = response.text().await.unwrap_or_default();
-                Err(Error::Api {
-                    status: status.as_u16(),
-                    message,
-                })
+        match response.status {
+            StatusCode::OK => Ok(self.data_stream(response)),
+            _ => Err(self.error_for(response).await),
+        }
+    }
+    
+    /// Сохранить превью в файл
+    pub async fn save_preview<P: AsRef<Path>>(
+        &self,
+        data_id: DataId,
+        output_path: P,
+    ) -> Result<()> {
+        let mut stream = self.stream_preview(data_id).await?;
+        let mut file = File::create(output_path).await?;
+        while let Some(chunk) = stream.next().await {
+            file.write_all(&chunk?).await?;
+        }
+        file.flush().await?;
+        Ok(())
+    }
+    
+    /// Получить данные файла
+    pub async fn get_data(&self, data_id: DataId) -> Result<Vec<u8>> {
+        self.stream_data(data_id).await?.read_all().await
+    }
+
+    /// Получить данные файла потоком
+    pub async fn stream_data(&self, data_id: DataId) -> Result<DataStream> {
+        let response = self
+            .execute_idempotent(|| {
+                Ok(self.request(
+                    "get_data",
+                    Method::GET,
+                    &format!("/api/data/{}", data_id),
+                    self.data_headers(AuthScope::Read, data_id)?,
+                ))
+            })
+            .await?;
+            
+        match response.status {
+            StatusCode::OK => Ok(self.data_stream(response)),
+            _ => Err(self.error_for(response).await),
+        }
+    }
+    
+    /// Скачать файл с прогрессом
+    pub async fn download_data<P: AsRef<Path>>(
+        &self,
+        data_id: DataId,
+        output_path: P,
+        progress: Option<ProgressCallback>,
+    ) -> Result<()> {
+        let options = TransferOptions {
+            progress: progress.map(Arc::new),
+            ..TransferOptions::default()
+        };
+        self.download_data_with(data_id, output_path, options).await
+    }
+
+    /// Скачать файл с отменой и прогрессом.
+    ///
+    /// При отмене или ошибке после начала записи недокачанный файл удаляется
+    /// или остается согласно [`TransferOptions::with_partial_file`].
+    pub async fn download_data_with<P: AsRef<Path>>(
+        &self,
+        data_id: DataId,
+        output_path: P,
+        options: TransferOptions,
+    ) -> Result<()> {
+        let output_path = output_path.as_ref();
+        let mut created = false;
+        let result = options
+            .run(self.download_to(data_id, output_path, options.progress.as_deref(), &mut created))
+            .await;
+        if result.is_err() && created && options.partial_file == PartialFile::Remove {
+            let _ = tokio::fs::remove_file(output_path).await;
+        }
+        result
+    }
+
+    /// Скачать файл; `created` отмечается перед созданием файла
+    async fn download_to(
+        &self,
+        data_id: DataId,
+        output_path: &Path,
+        progress: Option<&ProgressCallback>,
+        created: &mut bool,
+    ) -> Result<()> {
+        let response = self
+            .execute_idempotent(|| {
+                Ok(self.request(
+                    "download_data",
+                    Method::GET,
+                    &format!("/api/data/{}", data_id),
+                    self.data_headers(AuthScope::Read, data_id)?,
+                ))
+            })
+            .await?;
+            
+        match response.status {
+            StatusCode::OK => {
+                let total_size = response.content_length().unwrap_or(0);
+                    
+                *created = true;
+                let mut file = File::create(output_path).await?;
+                let mut downloaded = 0u64;
+                let mut stream = response.response.body;
+                
+                while let Some(chunk) = self.next_chunk(&mut stream).await? {
+                    file.write_all(&chunk).await?;
+                    downloaded += chunk.len() as u64;
+                    
+                    if let Some(callback) = progress {
+                        callback(downloaded, total_size);
+                    }
+                }
+                file.flush().await?;
+                
+                Ok(())
+            }
+            _ => Err(self.error_for(response).await),
+        }
+    }
+
+    /// Скачать файл с докачкой.
+    ///
+    /// Данные пишутся в `<путь>.part`, ожидаемый размер из [`DataInfo::size`] - рядом
+    /// в `<путь>.part.meta`. Повторный вызов после обрыва или отмены продолжает с конца
+    /// `.part` запросом `Range`; если сервер отвечает файлом целиком (200 вместо 206,
+    /// `Range` поддерживается только для видео), скачивание начинается заново. Обрыв
+    /// во время передачи докачивается сразу, не больше числа повторов [`RetryPolicy`].
+    /// Готовый файл переименовывается в `output_path`. [`TransferOptions::with_partial_file`]
+    /// не действует: недокачанный `.part` всегда остается для следующего вызова.
+    pub async fn download_data_resumable<P: AsRef<Path>>(
+        &self,
+        info: &DataInfo,
+        output_path: P,
+        options: TransferOptions,
+    ) -> Result<()> {
+        let output_path = output_path.as_ref();
+        let size = u64::try_from(info.size)
+            .map_err(|_| Error::Config(format!("invalid size {} of data {}", info.size, info.id)))?;
+        let download = PartialDownload {
+            data_id: info.id,
+            size,
+        };
+        let (part_path, meta_path) = PartialDownload::paths(output_path);
+        let mut resumes = 0;
+        loop {
+            let offset = download.prepare(&part_path, &meta_path).await?;
+            let mut written = 0;
+            match self.download_part(&download, &part_path, offset, &options, &mut written).await {
+                Ok(true) => break,
+                // `.part` очищен, начать с нуля
+                Ok(false) => {}
+                // Диапазон больше не подходит к файлу на сервере
+                Err(Error::InvalidRange(_)) if offset > 0 => {
+                    let _ = tokio::fs::remove_file(&meta_path).await;
+                }
+                Err(error) if error.is_resumable() && written > 0 && resumes < self.retry.max_retries => {
+                    resumes += 1;
+                }
+                Err(error) => return Err(error),
+            }
+        }
+        tokio::fs::rename(&part_path, output_path).await?;
+        let _ = tokio::fs::remove_file(&meta_path).await;
+        Ok(())
+    }
+
+    /// Дописать `.part` с `offset`; `written` считает записанные в этой попытке байты.
+    ///
+    /// `false` - сервер продолжил не с `offset`, `.part` очищен и скачивание надо начать
+    /// заново. Отмена во время передачи проверяется здесь, а не в [`TransferOptions::run`],
+    /// чтобы записанное успело попасть в `.part`.
+    async fn download_part(
+        &self,
+        download: &PartialDownload,
+        part_path: &Path,
+        offset: u64,
+        options: &TransferOptions,
+        written: &mut u64,
+    ) -> Result<bool> {
+        let data_id = download.data_id;
+        let mut headers = self.data_headers(AuthScope::Read, data_id)?;
+        if offset > 0 {
+            if offset == download.size {
+                return Ok(true);
+            }
+            headers.insert(RANGE, HeaderValue::from_str(&format!("bytes={}-", offset))?);
+        }
+        let response = options
+            .run(self.execute_idempotent(|| {
+                Ok(self.request(
+                    "download_data",
+                    Method::GET,
+                    &format!("/api/data/{}", data_id),
+                    headers.clone(),
+                ))
+            }))
+            .await?;
+
+        let resumed = response.header_str(CONTENT_RANGE).and_then(parse_content_range).map(|(start, _, _)| start);
+        let (mut file, mut position) = match response.status {
+            StatusCode::PARTIAL_CONTENT if offset > 0 && resumed == Some(offset) => {
+                (tokio::fs::OpenOptions::new().append(true).open(part_path).await?, offset)
+            }
+            StatusCode::PARTIAL_CONTENT if offset > 0 => {
+                File::create(part_path).await?;
+                return Ok(false);
+            }
+            // Сервер отдал файл целиком: начать заново
+            StatusCode::OK => (File::create(part_path).await?, 0),
+            _ => return Err(self.error_for(response).await),
+        };
+        let mut stream = response.response.body;
+        loop {
+            let chunk = match &options.cancellation {
+                Some(token) => tokio::select! {
+                    biased;
+                    _ = token.cancelled() => Err(Error::Cancelled),
+                    chunk = self.next_chunk(&mut stream) => chunk,
+                },
+                None => self.next_chunk(&mut stream).await,
+            };
+            let chunk = match chunk {
+                Ok(Some(chunk)) => chunk,
+                Ok(None) => break,
+                Err(error) => {
+                    // Записанное до отмены или обрыва должно оказаться в `.part`
+                    file.flush().await?;
+                    return Err(error);
+                }
+            };
+            position += chunk.len() as u64;
+            if position > download.size {
+                drop(file);
+                let _ = tokio::fs::remove_file(part_path).await;
+                return Err(Error::Io(std::io::Error::new(
+                    std::io::ErrorKind::InvalidData,
+                    format!("data {} is larger than expected {} bytes", data_id, download.size),
+                )));
+            }
+            file.write_all(&chunk).await?;
+            *written += chunk.len() as u64;
+            if let Some(callback) = &options.progress {
+                callback(position, download.size);
+            }
+        }
+        file.flush().await?;
+
+        if position < download.size {
+            return Err(Error::Io(std::io::Error::new(
+                std::io::ErrorKind::UnexpectedEof,
+                format!("download ended at {} of {} bytes", position, download.size),
+            )));
+        }
+        Ok(true)
+    }
+    
+    /// Скачать видео несколькими параллельными запросами диапазонов.
+    ///
+    /// Файл размером [`DataInfo::size`] делится на `segments` частей не меньше
+    /// [`PARALLEL_SEGMENT_MIN`], части пишутся в заранее выделенный файл, в конце
+    /// сверяется длина. Не видео, небольшие файлы и ответ 200 вместо 206 на первый
+    /// диапазон скачиваются одним потоком. Недокачанный файл удаляется или остается
+    /// согласно [`TransferOptions::with_partial_file`].
+    pub async fn download_data_parallel<P: AsRef<Path>>(
+        &self,
+        info: &DataInfo,
+        output_path: P,
+        segments: usize,
+        options: TransferOptions,
+    ) -> Result<()> {
+        let output_path = output_path.as_ref();
+        let size = u64::try_from(info.size)
+            .map_err(|_| Error::Config(format!("invalid size {} of data {}", info.size, info.id)))?;
+        let ranges = segment_ranges(size, segments);
+        if info.kind() != DataKind::Video || ranges.len() < 2 {
+            return self.download_data_with(info.id, output_path, options).await;
+        }
+
+        let mut created = false;
+        let result = options
+            .run(self.download_segments(info.id, size, &ranges, output_path, options.progress.as_deref(), &mut created))
+            .await;
+        if result.is_err() && created && options.partial_file == PartialFile::Remove {
+            let _ = tokio::fs::remove_file(output_path).await;
+        }
+        result
+    }
+
+    /// Скачать диапазоны в файл; `created` отмечается перед созданием файла
+    async fn download_segments(
+        &self,
+        data_id: DataId,
+        size: u64,
+        ranges: &[(u64, u64)],
+        output_path: &Path,
+        progress: Option<&ProgressCallback>,
+        created: &mut bool,
+    ) -> Result<()> {
+        let downloaded = std::sync::atomic::AtomicU64::new(0);
+        let first = self.request_range("download_segment", data_id, ranges[0].0, Some(ranges[0].1)).await?;
+        *created = true;
+        if first.status == StatusCode::OK {
+            // Сервер не поддерживает диапазоны: первый ответ уже содержит весь файл
+            File::create(output_path).await?;
+            self.write_segment(first, output_path, (0, size - 1), &downloaded, size, progress).await?;
+        } else {
+            let file = File::create(output_path).await?;
+            file.set_len(size).await?;
+            drop(file);
+
+            let first = self.write_segment(first, output_path, ranges[0], &downloaded, size, progress);
+            let rest = ranges[1..].iter().map(|range| {
+                let downloaded = &downloaded;
+                async move {
+                    let response = self.request_range("download_segment", data_id, range.0, Some(range.1)).await?;
+                    self.write_segment(response, output_path, *range, downloaded, size, progress).await
+                }
+            });
+            futures_util::future::try_join(first, futures_util::future::try_join_all(rest)).await?;
+        }
+
+        let written = downloaded.into_inner();
+        let length = tokio::fs::metadata(output_path).await?.len();
+        if written != size || length != size {
+            return Err(Error::Io(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!("downloaded {} bytes into file of {} bytes, expected {}", written, length, size),
+            )));
+        }
+        Ok(())
+    }
+
+    /// Запросить диапазон, без `end` - до конца файла; 200 возвращается как есть,
+    /// прочие статусы кроме 206 - ошибка
+    async fn request_range(
+        &self,
+        operation: &'static str,
+        data_id: DataId,
+        start: u64,
+        end: Option<u64>,
+    ) -> Result<Exchange> {
+        let mut headers = self.data_headers(AuthScope::Read, data_id)?;
+        let end = end.map(|end| end.to_string()).unwrap_or_default();
+        headers.insert(RANGE, HeaderValue::from_str(&format!("bytes={}-{}", start, end))?);
+        let response = self
+            .execute_idempotent(|| {
+                Ok(self.request(
+                    operation,
+                    Method::GET,
+                    &format!("/api/data/{}", data_id),
+                    headers.clone(),
+                ))
+            })
+            .await?;
+        match response.status {
+            StatusCode::OK | StatusCode::PARTIAL_CONTENT => Ok(response),
+            _ => Err(self.error_for(response).await),
+        }
+    }
+
+    /// Открыть файл записи для чтения с произвольным доступом, см. [`RemoteMedia`]
+    pub async fn open_media(&self, data_id: DataId, options: RemoteMediaOptions) -> Result<RemoteMedia> {
+        RemoteMedia::open(self.clone(), data_id, options).await
+    }
+
+    /// Записать тело ответа в файл с позиции `start`, проверив диапазон и объем
+    async fn write_segment(
+        &self,
+        response: Exchange,
+        output_path: &Path,
+        (start, end): (u64, u64),
+        downloaded: &std::sync::atomic::AtomicU64,
+        size: u64,
+        progress: Option<&ProgressCallback>,
+    ) -> Result<()> {
+        let expected = Some((start, end, Some(size)));
+        let ranged = response.header_str(CONTENT_RANGE).and_then(parse_content_range);
+        if response.status == StatusCode::PARTIAL_CONTENT && ranged != expected
+            || response.status == StatusCode::OK && start != 0
+        {
+            return Err(Error::Io(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!("server answered bytes {}-{} with {:?}", start, end, ranged),
+            )));
+        }
+
+        let mut file = tokio::fs::OpenOptions::new().write(true).open(output_path).await?;
+        file.seek(std::io::SeekFrom::Start(start)).await?;
+        let length = end - start + 1;
+        let mut written = 0u64;
+        let mut stream = response.response.body;
+        while let Some(chunk) = self.next_chunk(&mut stream).await? {
+            written += chunk.len() as u64;
+            if written > length {
+                return Err(Error::Io(std::io::Error::new(
+                    std::io::ErrorKind::InvalidData,
+                    format!("bytes {}-{} returned more than {} bytes", start, end, length),
+                )));
+            }
+            file.write_all(&chunk).await?;
+            let total = downloaded.fetch_add(chunk.len() as u64, std::sync::atomic::Ordering::Relaxed)
+                + chunk.len() as u64;
+            if let Some(callback) = progress {
+                callback(total, size);
+            }
+        }
+        file.flush().await?;
+
+        if written < length {
+            return Err(Error::Io(std::io::Error::new(
+                std::io::ErrorKind::UnexpectedEof,
+                format!("bytes {}-{} ended after {} bytes", start, end, written),
+            )));
+        }
+        Ok(())
+    }
+    
+    /// Получить видео поток с поддержкой Range
+    pub async fn get_video_stream(
+        &self,
+        data_id: DataId,
+        range_start: Option<u64>,
+        range_end: Option<u64>,
+    ) -> Result<(Vec<u8>, VideoHeaders)> {
+        let stream = self.stream_video(data_id, range_start, range_end).await?;
+        let video_headers = VideoHeaders {
+            content_range: stream.header_str(CONTENT_RANGE).map(|s| s.to_string()),
+            content_length: stream.header_str(CONTENT_LENGTH).map(|s| s.to_string()),
+            content_type: stream.header_str(CONTENT_TYPE).map(|s| s.to_string()),
+        };
+        
+        let data = stream.read_all().await?;
+        Ok((data, video_headers))
+    }
+
+    /// Получить видео потоком с поддержкой Range; статус 206 для диапазона
+    pub async fn stream_video(
+        &self,
+        data_id: DataId,
+        range_start: Option<u64>,
+        range_end: Option<u64>,
+    ) -> Result<DataStream> {
+        let mut headers = self.data_headers(AuthScope::Read, data_id)?;
+        
+        if range_start.is_some() || range_end.is_some() {
+            let range_value = match (range_start, range_end) {
+                (Some(start), Some(end)) => format!("bytes={}-{}", start, end),
+                (Some(start), None) => format!("bytes={}-", start),
+                (None, Some(end)) => format!("bytes=-{}", end),
+                _ => unreachable!(),
+            };
+            headers.insert(RANGE, HeaderValue::from_str(&range_value)?);
+        }
+        
+        let response = self
+            .execute_idempotent(|| {
+                Ok(self.request(
+                    "get_video_stream",
+                    Method::GET,
+                    &format!("/api/data/{}", data_id),
+                    headers.clone(),
+                ))
+            })
+            .await?;
+            
+        let status = response.status;
+        if status != StatusCode::OK && status != StatusCode::PARTIAL_CONTENT {
+            return Err(self.error_for(response).await);
+        }
+        Ok(self.data_stream(response))
+    }
+    
+    /// Удалить файл
+    pub async fn delete_data(&self, data_id: DataId) -> Result<()> {
+        let response = self
+            .execute_idempotent(|| {
+                Ok(self.request(
+                    "delete_data",
+                    Method::DELETE,
+                    &format!("/api/data/{}", data_id),
+                    self.data_headers(AuthScope::Write, data_id)?,
+                ))
+            })
+            .await?;
+            
+        match response.status {
+            StatusCode::OK => {
+                self.album_credentials.forget_data(data_id);
+                Ok(())
+            }
+            _ => Err(self.error_for(response).await),
+        }
+    }
+}
+
+/// Ответ вместе с запросом, к которому он относится
+struct Exchange {
+    operation: &'static str,
+    method: Method,
+    url: String,
+    response: ApiResponse,
+}
+
+impl std::ops::Deref for Exchange {
+    type Target = ApiResponse;
+
+    fn deref(&self) -> &ApiResponse {
+        &self.response
+    }
+}
+
+/// Итог неидемпотентного запроса со сверкой
+enum Reconciled<T> {
+    /// Ответ сервера на последнюю попытку
+    Response(Exchange),
+    /// Результат предыдущей попытки, найденный сверкой
+    Found(T),
+}
+
+/// Заголовки видео ответа
+#[derive(Debug, Clone)]
+pub struct VideoHeaders {
+    pub content_range: Option<String>,
+    pub content_length: Option<String>,
+    pub content_type: Option<String>,
+}
+
+/// Тело ответа потоком вместе со статусом и заголовками.
+///
+/// Порции читаются по мере поступления, без сбора тела в память; таймаут чтения
+/// клиента действует на каждую порцию. [`DataStream::into_reader`] дает `AsyncRead`.
+pub struct DataStream {
+    status: StatusCode,
+    headers: HeaderMap,
+    body: ResponseBody,
+}
+
+impl std::fmt::Debug for DataStream {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("DataStream")
+            .field("status", &self.status)
+            .field("headers", &self.headers)
+            .finish_non_exhaustive()
+    }
+}
+
+impl DataStream {
+    /// Статус ответа: 200 или 206 для диапазона видео
+    pub fn status(&self) -> StatusCode {
+        self.status
+    }
+
+    /// Заголовки ответа
+    pub fn headers(&self) -> &HeaderMap {
+        &self.headers
+    }
+
+    /// Длина тела из `Content-Length`
+    pub fn content_length(&self) -> Option<u64> {
+        self.header_str(CONTENT_LENGTH).and_then(|value| value.parse().ok())
+    }
+
+    /// `Content-Type` ответа
+    pub fn content_type(&self) -> Option<&str> {
+        self.header_str(CONTENT_TYPE)
+    }
+
+    /// `Content-Range` ответа на запрос диапазона
+    pub fn content_range(&self) -> Option<&str> {
+        self.header_str(CONTENT_RANGE)
+    }
+
+    fn header_str(&self, name: impl reqwest::header::AsHeaderName) -> Option<&str> {
+        self.headers.get(name).and_then(|value| value.to_str().ok())
+    }
+
+    /// Тело как `AsyncRead`; ошибки клиента приходят как [`std::io::Error`]
+    pub fn into_reader(self) -> impl AsyncRead + Send + Unpin {
+        tokio_util::io::StreamReader::new(self.body.map(|chunk| chunk.map_err(io_error)))
+    }
+
+    /// Прочитать тело целиком
+    pub async fn read_all(mut self) -> Result<Vec<u8>> {
+        let mut body = Vec::with_capacity(self.content_length().unwrap_or(0) as usize);
+        while let Some(chunk) = self.body.next().await {
+            body.extend_from_slice(&chunk?);
+        }
+        Ok(body)
+    }
+}
+
+impl Stream for DataStream {
+    type Item = Result<Bytes>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut std::task::Context<'_>) -> std::task::Poll<Option<Self::Item>> {
+        self.body.as_mut().poll_next(cx)
+    }
+}
+
+/// Ошибка клиента для интерфейсов `std::io`; ошибка ввода-вывода передается как есть
+fn io_error(error: Error) -> std::io::Error {
+    match error {
+        Error::Io(error) => error,
+        error => std::io::Error::other(error),
+    }
+}
+
+// Докачка
+
+/// Сведения о недокачанном файле в `<путь>.part.meta`
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PartialDownload {
+    data_id: DataId,
+    size: u64,
+}
+
+impl PartialDownload {
+    /// Пути `.part` и `.part.meta` для файла
+    fn paths(output_path: &Path) -> (PathBuf, PathBuf) {
+        let mut part = output_path.as_os_str().to_owned();
+        part.push(".part");
+        let mut meta = part.clone();
+        meta.push(".meta");
+        (part.into(), meta.into())
+    }
+
+    /// Смещение для продолжения: конец `.part` той же записи того же размера,
+    /// иначе `.part` очищается и начинается заново
+    async fn prepare(&self, part_path: &Path, meta_path: &Path) -> Result<u64> {
+        let saved = tokio::fs::read(meta_path)
+            .await
+            .ok()
+            .and_then(|meta| serde_json::from_slice::<PartialDownload>(&meta).ok());
+        if saved.as_ref() == Some(self) {
+            if let Ok(metadata) = tokio::fs::metadata(part_path).await {
+                if metadata.len() <= self.size {
+                    return Ok(metadata.len());
+                }
+            }
+        }
+        File::create(part_path).await?;
+        let meta = serde_json::to_vec(self).map_err(std::io::Error::from)?;
+        tokio::fs::write(meta_path, meta).await?;
+        Ok(0)
+    }
+}
+
+/// Разобрать `Content-Range: bytes начало-конец/размер`; размер `*` неизвестен
+fn parse_content_range(value: &str) -> Option<(u64, u64, Option<u64>)> {
+    let (range, total) = value.trim().strip_prefix("bytes ")?.split_once('/')?;
+    let (start, end) = range.split_once('-')?;
+    let (start, end): (u64, u64) = (start.trim().parse().ok()?, end.trim().parse().ok()?);
+    let total = match total.trim() {
+        "*" => None,
+        total => Some(total.parse().ok()?),
+    };
+    (start <= end).then_some((start, end, total))
+}
+
+// Параллельное скачивание
+
+/// Наименьший диапазон при параллельном скачивании, байт
+pub const PARALLEL_SEGMENT_MIN: u64 = 1024 * 1024;
+
+/// Разбить `size` байт на не больше `segments` диапазонов `(начало, конец)` включительно
+fn segment_ranges(size: u64, segments: usize) -> Vec<(u64, u64)> {
+    let segments = (segments as u64).min(size / PARALLEL_SEGMENT_MIN).max(1);
+    let length = size.div_ceil(segments);
+    (0..segments)
+        .map(|index| index * length)
+        .take_while(|start| *start < size)
+        .map(|start| (start, (start + length).min(size) - 1))
+        .collect()
+}
+
+// Чтение с произвольным доступом
+
+/// Настройки блоков [`RemoteMedia`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RemoteMediaOptions {
+    block_size: u64,
+    read_ahead: usize,
+    cache_blocks: usize,
+}
+
+impl Default for RemoteMediaOptions {
+    fn default() -> Self {
+        Self {
+            block_size: 1024 * 1024,
+            read_ahead: 2,
+            cache_blocks: 16,
+        }
+    }
+}
+
+impl RemoteMediaOptions {
+    /// Блоки по 1 МиБ, упреждение на 2 блока, кэш на 16 блоков
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Размер блока, байт (не меньше одного)
+    pub fn with_block_size(mut self, block_size: u64) -> Self {
+        self.block_size = block_size.max(1);
+        self
+    }
+
+    /// Сколько следующих блоков запрашивать заранее, 0 - без упреждения
+    pub fn with_read_ahead(mut self, blocks: usize) -> Self {
+        self.read_ahead = blocks;
+        self
+    }
+
+    /// Сколько блоков хранить в кэше; не меньше текущего блока с упреждением
+    pub fn with_cache_blocks(mut self, blocks: usize) -> Self {
+        self.cache_blocks = blocks;
+        self
+    }
+
+    fn cache_limit(&self) -> usize {
+        self.cache_blocks.max(self.read_ahead + 1)
+    }
+}
+
+/// Файл записи на сервере как `AsyncRead` + `AsyncSeek`.
+///
+/// Читает блоками запросами `Range`, недавние блоки хранит в кэше. Следующие за
+/// текущим блоки загружаются заранее фоновыми задачами tokio, пока вызывающий
+/// обрабатывает прочитанное; задачи вне окна упреждения и при удалении читателя
+/// отменяются. Ошибка блока упреждения не запрашивается повторно, а возвращается,
+/// когда чтение дойдет до блока; следующее чтение запросит его снова.
+/// Читатель владеет клоном клиента и не заимствует его.
+///
+/// Сервер поддерживает `Range` только для видео: для прочих записей открытие
+/// завершается ошибкой `ErrorKind::Unsupported`, их читает [`ZeroGalleryClient::stream_data`].
+pub struct RemoteMedia {
+    client: Arc<ZeroGalleryClient>,
+    data_id: DataId,
+    options: RemoteMediaOptions,
+    length: u64,
+    content_type: Option<String>,
+    position: u64,
+    /// Блоки по давности использования, в конце - последний
+    cache: VecDeque<(u64, Bytes)>,
+    pending: Vec<(u64, tokio::task::JoinHandle<Result<Bytes>>)>,
+    /// Ошибки блоков упреждения до перехода чтения к ним
+    failed: HashMap<u64, std::io::Error>,
+}
+
+impl std::fmt::Debug for RemoteMedia {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RemoteMedia")
+            .field("data_id", &self.data_id)
+            .field("length", &self.length)
+            .field("position", &self.position)
+            .finish_non_exhaustive()
+    }
+}
+
+impl RemoteMedia {
+    /// Узнать размер и тип запросом `bytes=0-`, первый блок сразу попадает в кэш
+    async fn open(client: ZeroGalleryClient, data_id: DataId, options: RemoteMediaOptions) -> Result<Self> {
+        let response = client.request_range("read_media", data_id, 0, None).await?;
+        if response.status == StatusCode::OK {
+            return Err(Error::Io(std::io::Error::new(
+                std::io::ErrorKind::Unsupported,
+                format!("record {} is not served with byte ranges", data_id),
+            )));
+        }
+        let ranged = response.header_str(CONTENT_RANGE).and_then(parse_content_range);
+        let length = match ranged {
+            Some((0, _, Some(length))) => length,
+            _ => {
+                return Err(Error::Io(std::io::Error::new(
+                    std::io::ErrorKind::InvalidData,
+                    format!("server answered bytes 0- with {:?}", ranged),
+                )))
+            }
+        };
+        let content_type = response.header_str(CONTENT_TYPE).map(|s| s.to_string());
+
+        // Остаток тела сверх первого блока не читается
+        let first = options.block_size.min(length) as usize;
+        let mut block = Vec::with_capacity(first);
+        let mut body = response.response.body;
+        while block.len() < first {
+            match client.next_chunk(&mut body).await? {
+                Some(chunk) => block.extend_from_slice(&chunk[..chunk.len().min(first - block.len())]),
+                None => return Err(Error::Io(std::io::ErrorKind::UnexpectedEof.into())),
+            }
+        }
+        let mut media = Self {
+            client: Arc::new(client),
+            data_id,
+            options,
+            length,
+            content_type,
+            position: 0,
+            cache: VecDeque::new(),
+            pending: Vec::new(),
+            failed: HashMap::new(),
+        };
+        media.insert(0, block.into());
+        media.prefetch(0);
+        Ok(media)
+    }
+
+    /// Идентификатор записи
+    pub fn data_id(&self) -> DataId {
+        self.data_id
+    }
+
+    /// Размер файла, байт
+    pub fn len(&self) -> u64 {
+        self.length
+    }
+
+    /// Файл пуст
+    pub fn is_empty(&self) -> bool {
+        self.length == 0
+    }
+
+    /// `Content-Type` ответа сервера
+    pub fn content_type(&self) -> Option<&str> {
+        self.content_type.as_deref()
+    }
+
+    /// Текущая позиция чтения
+    pub fn position(&self) -> u64 {
+        self.position
+    }
+
+    /// Запустить загрузку блока `index` фоновой задачей
+    fn fetch(&self, index: u64) -> tokio::task::JoinHandle<Result<Bytes>> {
+        let (client, data_id, length) = (self.client.clone(), self.data_id, self.length);
+        let start = index * self.options.block_size;
+        let end = (start + self.options.block_size).min(length) - 1;
+        tokio::spawn(async move {
+            let response = client.request_range("read_media", data_id, start, Some(end)).await?;
+            let ranged = response.header_str(CONTENT_RANGE).and_then(parse_content_range);
+            if response.status != StatusCode::PARTIAL_CONTENT || ranged != Some((start, end, Some(length))) {
+                return Err(Error::Io(std::io::Error::new(
+                    std::io::ErrorKind::InvalidData,
+                    format!("server answered bytes {}-{} with {:?}", start, end, ranged),
+                )));
+            }
+            let block = client.read_body(response).await?;
+            if block.len() as u64 != end - start + 1 {
+                return Err(Error::Io(std::io::Error::new(
+                    std::io::ErrorKind::UnexpectedEof,
+                    format!("bytes {}-{} returned {} bytes", start, end, block.len()),
+                )));
+            }
+            Ok(block.into())
+        })
+    }
+
+    /// Запросить блок `index` и упреждение за ним, отменив запросы вне окна
+    fn prefetch(&mut self, index: u64) {
+        if self.length == 0 {
+            return;
+        }
+        let last = (self.length - 1) / self.options.block_size;
+        let window = index..=(index + self.options.read_ahead as u64).min(last);
+        self.pending.retain(|(pending, task)| {
+            let keep = window.contains(pending);
+            if !keep {
+                task.abort();
+            }
+            keep
+        });
+        self.failed.retain(|failed, _| window.contains(failed));
+        for wanted in window {
+            let requested = self.pending.iter().any(|(pending, _)| *pending == wanted)
+                || self.cache.iter().any(|(cached, _)| *cached == wanted)
+                || self.failed.contains_key(&wanted);
+            if !requested {
+                let task = self.fetch(wanted);
+                self.pending.push((wanted, task));
+            }
+        }
+    }
+
+    /// Блок из кэша с отметкой об использовании
+    fn cached(&mut self, index: u64) -> Option<Bytes> {
+        let at = self.cache.iter().position(|(cached, _)| *cached == index)?;
+        let entry = self.cache.remove(at)?;
+        let block = entry.1.clone();
+        self.cache.push_back(entry);
+        Some(block)
+    }
+
+    /// Положить блок в кэш, вытеснив давно не использованные
+    fn insert(&mut self, index: u64, block: Bytes) {
+        self.cache.retain(|(cached, _)| *cached != index);
+        self.cache.push_back((index, block));
+        while self.cache.len() > self.options.cache_limit() {
+            self.cache.pop_front();
+        }
+    }
+}
+
+impl Drop for RemoteMedia {
+    fn drop(&mut self) {
+        for (_, task) in &self.pending {
+            task.abort();
+        }
+    }
+}
+
+impl AsyncRead for RemoteMedia {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> std::task::Poll<std::io::Result<()>> {
+        let this = self.get_mut();
+        if this.position >= this.length || buf.remaining() == 0 {
+            return std::task::Poll::Ready(Ok(()));
+        }
+        let block_size = this.options.block_size;
+        let index = this.position / block_size;
+        if let Some(error) = this.failed.remove(&index) {
+            return std::task::Poll::Ready(Err(error));
+        }
+        this.prefetch(index);
+
+        // Забрать завершенные загрузки
+        let mut failed = None;
+        let mut at = 0;
+        while at < this.pending.len() {
+            match std::future::Future::poll(Pin::new(&mut this.pending[at].1), cx) {
+                std::task::Poll::Ready(result) => {
+                    let (fetched, _) = this.pending.swap_remove(at);
+                    match result.map_err(std::io::Error::from).and_then(|block| block.map_err(io_error)) {
+                        Ok(block) => this.insert(fetched, block),
+                        Err(error) if fetched == index => failed = Some(error),
+                        Err(error) => {
+                            this.failed.insert(fetched, error);
+                        }
+                    }
+                }
+                std::task::Poll::Pending => at += 1,
+            }
+        }
+        if let Some(error) = failed {
+            return std::task::Poll::Ready(Err(error));
+        }
+
+        match this.cached(index) {
+            Some(block) => {
+                let offset = (this.position - index * block_size) as usize;
+                let count = buf.remaining().min(block.len() - offset);
+                buf.put_slice(&block[offset..offset + count]);
+                this.position += count as u64;
+                std::task::Poll::Ready(Ok(()))
+            }
+            None => std::task::Poll::Pending,
+        }
+    }
+}
+
+impl AsyncSeek for RemoteMedia {
+    fn start_seek(self: Pin<&mut Self>, position: std::io::SeekFrom) -> std::io::Result<()> {
+        let this = self.get_mut();
+        let target = match position {
+            std::io::SeekFrom::Start(offset) => Some(offset),
+            std::io::SeekFrom::End(offset) => this.length.checked_add_signed(offset),
+            std::io::SeekFrom::Current(offset) => this.position.checked_add_signed(offset),
+        };
+        this.position = target.ok_or_else(|| {
+            std::io::Error::new(std::io::ErrorKind::InvalidInput, "invalid seek to a negative position")
+        })?;
+        Ok(())
+    }
+
+    fn poll_complete(self: Pin<&mut Self>, _cx: &mut std::task::Context<'_>) -> std::task::Poll<std::io::Result<u64>> {
+        std::task::Poll::Ready(Ok(self.position))
+    }
+}
+
+// Пакетная загрузка
+
+/// Ответ загрузки: сервер возвращает число для формы из одного файла и массив иначе
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum UploadResponse {
+    One(DataId),
+    Many(Vec<DataId>),
+}
+
+impl UploadResponse {
+    fn into_ids(self) -> Vec<DataId> {
+        match self {
+            UploadResponse::One(id) => vec![id],
+            UploadResponse::Many(ids) => ids,
+        }
+    }
+}
+
+/// Разбиение файлов на формы для [`ZeroGalleryClient::upload_batched`].
+///
+/// Файлы идут в формы по порядку, пока не исчерпан бюджет числа файлов или байт.
+/// Файл больше бюджета байт и файл неизвестного размера отправляются отдельной формой.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UploadBatchPlanner {
+    max_files: usize,
+    max_bytes: u64,
+}
+
+impl Default for UploadBatchPlanner {
+    fn default() -> Self {
+        Self {
+            max_files: 16,
+            max_bytes: 32 * 1024 * 1024,
+        }
+    }
+}
+
+impl UploadBatchPlanner {
+    /// Не больше 16 файлов и 32 МиБ в форме
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Наибольшее число файлов в форме (не меньше одного)
+    pub fn with_max_files(mut self, max_files: usize) -> Self {
+        self.max_files = max_files.max(1);
+        self
+    }
+
+    /// Наибольший объем содержимого формы, байт
+    pub fn with_max_bytes(mut self, max_bytes: u64) -> Self {
+        self.max_bytes = max_bytes.max(1);
+        self
+    }
+
+    /// Диапазоны номеров источников, по форме на диапазон
+    pub fn plan(&self, sources: &[UploadSource]) -> Vec<std::ops::Range<usize>> {
+        let mut batches = Vec::new();
+        let mut start = 0;
+        let mut bytes = 0;
+        for (index, source) in sources.iter().enumerate() {
+            let length = source.length();
+            let full = index - start == self.max_files
+                || length.is_none_or(|length| bytes + length > self.max_bytes);
+            if index > start && full {
+                batches.push(start..index);
+                start = index;
+                bytes = 0;
+            }
+            match length {
+                Some(length) => bytes += length,
+                None => {
+                    batches.push(index..index + 1);
+                    start = index + 1;
+                    bytes = 0;
+                }
+            }
+        }
+        if start < sources.len() {
+            batches.push(start..sources.len());
+        }
+        batches
+    }
+}
+
+/// Результат загрузки одного входа пакета
+#[derive(Debug)]
+pub struct BulkUploadItem {
+    /// Имя файла (для неверного пути - сам путь)
+    pub file_name: String,
+    pub result: Result<DataId>,
+    /// Отправлено байт содержимого в последней попытке
+    pub bytes: u64,
+}
+
+/// Отчет пакетной загрузки; элементы идут в порядке входов
+#[derive(Debug)]
+pub struct BulkUploadReport {
+    pub items: Vec<BulkUploadItem>,
+    /// Байт содержимого в успешно загруженных файлах
+    pub bytes_uploaded: u64,
+    pub duration: Duration,
+}
+
+impl BulkUploadReport {
+    /// Число загруженных файлов
+    pub fn succeeded(&self) -> usize {
+        self.items.iter().filter(|item| item.result.is_ok()).count()
+    }
+
+    /// Число файлов с ошибкой
+    pub fn failed(&self) -> usize {
+        self.items.len() - self.succeeded()
+    }
+
+    /// Все файлы загружены
+    pub fn is_success(&self) -> bool {
+        self.items.iter().all(|item| item.result.is_ok())
+    }
+
+    /// Файлы с ошибкой
+    pub fn failures(&self) -> impl Iterator<Item = &BulkUploadItem> {
+        self.items.iter().filter(|item| item.result.is_err())
+    }
+}
+
+/// Загрузка множества файлов отдельными запросами с ограниченным параллелизмом.
+///
+/// Ошибка одного файла не прерывает остальные: каждый вход получает свой результат
+/// в [`BulkUploadReport`]. Прогресс из [`TransferOptions`] считается по всему пакету,
+/// отмена прерывает идущие загрузки и помечает оставшиеся как [`Error::Cancelled`].
+pub struct BulkUploader<'a> {
+    client: &'a ZeroGalleryClient,
+    target: UploadTarget,
+    concurrency: usize,
+    options: TransferOptions,
+}
+
+impl<'a> BulkUploader<'a> {
+    /// Загрузчик в цель, по 4 файла одновременно
+    pub fn new(client: &'a ZeroGalleryClient, target: impl Into<UploadTarget>) -> Self {
+        Self {
+            client,
+            target: target.into(),
+            concurrency: 4,
+            options: TransferOptions::new(),
+        }
+    }
+
+    /// Сколько файлов загружать одновременно (не меньше одного)
+    pub fn with_concurrency(mut self, concurrency: usize) -> Self {
+        self.concurrency = concurrency.max(1);
+        self
+    }
+
+    /// Отмена и прогресс для всего пакета
+    pub fn with_options(mut self, options: TransferOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// Загрузить файлы с диска
+    pub async fn upload_paths<P: AsRef<Path>>(&self, paths: impl IntoIterator<Item = P>) -> BulkUploadReport {
+        let inputs = paths
+            .into_iter()
+            .map(|path| {
+                let path = path.as_ref();
+                UploadSource::from_path(path).map_err(|error| (path.display().to_string(), error))
+            })
+            .collect();
+        self.run(inputs).await
+    }
+
+    /// Загрузить файлы из источников
+    pub async fn upload(&self, sources: impl IntoIterator<Item = UploadSource>) -> BulkUploadReport {
+        self.run(sources.into_iter().map(Ok).collect()).await
+    }
+
+    async fn run(&self, inputs: Vec<std::result::Result<UploadSource, (String, Error)>>) -> BulkUploadReport {
+        let started = Instant::now();
+        let tracker = UploadTracker::new(&self.options, inputs.iter().flatten());
+        let mut items: Vec<Option<BulkUploadItem>> = Vec::new();
+        let mut sources = Vec::new();
+        for input in inputs {
+            match input {
+                Ok(source) => {
+                    sources.push((items.len(), source));
+                    items.push(None);
+                }
+                Err((file_name, error)) => items.push(Some(BulkUploadItem {
+                    file_name,
+                    result: Err(error),
+                    bytes: 0,
+                })),
             }
         }
+
+        let uploads = futures_util::stream::iter(sources.into_iter().enumerate())
+            .map(|(tracked, (index, source))| {
+                let tracker = &tracker;
+                async move {
+                    let result = self
+                        .options
+                        .run(self.client.upload_tracked(
+                            "upload_bulk",
+                            &source,
+                            self.target,
+                            Some((tracker, tracked)),
+                        ))
+                        .await;
+                    let item = BulkUploadItem {
+                        file_name: source.file_name().to_string(),
+                        result,
+                        bytes: tracker.sent(tracked),
+                    };
+                    (index, item)
+                }
+            })
+            .buffer_unordered(self.concurrency)
+            .collect::<Vec<_>>()
+            .await;
+        for (index, item) in uploads {
+            items[index] = Some(item);
+        }
+
+        let items: Vec<BulkUploadItem> = items.into_iter().flatten().collect();
+        let bytes_uploaded = items
+            .iter()
+            .filter(|item| item.result.is_ok())
+            .map(|item| item.bytes)
+            .sum();
+        BulkUploadReport {
+            items,
+            bytes_uploaded,
+            duration: started.elapsed(),
+        }
+    }
+}
+
+/// Синхронный клиент поверх асинхронного
+#[cfg(feature = "blocking")]
+pub mod blocking {
+    use super::*;
+    use tokio::runtime::Runtime;
+
+    /// Синхронный клиент ZeroGallery API.
+    ///
+    /// Каждый вызов выполняется на собственном однопоточном рантайме tokio клиента.
+    /// Вызывать методы изнутри асинхронного контекста нельзя: `block_on` паникует.
+    pub struct ZeroGalleryBlockingClient {
+        inner: ZeroGalleryClient,
+        runtime: Arc<Runtime>,
+    }
+
+    impl std::fmt::Debug for ZeroGalleryBlockingClient {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            f.debug_struct("ZeroGalleryBlockingClient").finish_non_exhaustive()
+        }
+    }
+
+    impl ZeroGalleryBlockingClient {
+        /// Обернуть асинхронный клиент
+        pub fn from_async(inner: ZeroGalleryClient) -> Result<Self> {
+            let runtime = tokio::runtime::Builder::new_current_thread()
+                .enable_all()
+                .build()?;
+            Ok(Self {
+                inner,
+                runtime: Arc::new(runtime),
+            })
+        }
+
+        /// Создать новый клиент.
+        ///
+        /// Паникует, если HTTP-клиент или рантайм не удалось создать;
+        /// [`ZeroGalleryClientBuilder::build_blocking`] возвращает ошибку вместо паники.
+        pub fn new(base_url: impl Into<String>) -> Self {
+            ZeroGalleryClient::builder(base_url)
+                .build_blocking()
+                .expect("Failed to create blocking client")
+        }
+
+        /// Создать клиент по именованному профилю, см. [`Profile::load`]
+        pub fn from_profile(name: &str) -> Result<Self> {
+            Self::from_async(ZeroGalleryClient::from_profile(name)?)
+        }
+
+        /// Создать клиент с токеном доступа.
+        ///
+        /// Паникует, если HTTP-клиент или рантайм не удалось создать;
+        /// [`ZeroGalleryClientBuilder::build_blocking`] возвращает ошибку вместо паники.
+        pub fn with_token(base_url: impl Into<String>, access_token: Option<String>) -> Self {
+            Self::with_credentials(
+                base_url,
+                Credentials {
+                    access_token,
+                    ..Credentials::default()
+                },
+            )
+        }
+
+        /// Создать клиент с учетными данными.
+        ///
+        /// Паникует, если HTTP-клиент или рантайм не удалось создать;
+        /// [`ZeroGalleryClientBuilder::build_blocking`] возвращает ошибку вместо паники.
+        pub fn with_credentials(base_url: impl Into<String>, credentials: Credentials) -> Self {
+            ZeroGalleryClient::builder(base_url)
+                .credentials(credentials)
+                .build_blocking()
+                .expect("Failed to create blocking client")
+        }
+
+        /// Асинхронный клиент: настройки, метрики, реестр токенов
+        pub fn client(&self) -> &ZeroGalleryClient {
+            &self.inner
+        }
+
+        /// Асинхронный клиент для изменения настроек
+        pub fn client_mut(&mut self) -> &mut ZeroGalleryClient {
+            &mut self.inner
+        }
+
+        /// Забрать асинхронный клиент
+        pub fn into_async(self) -> ZeroGalleryClient {
+            self.inner
+        }
+
+        /// Получить версию API
+        pub fn get_version(&self) -> Result<String> {
+            self.runtime.block_on(self.inner.get_version())
+        }
+
+        /// Получить список альбомов
+        pub fn get_albums(&self) -> Result<Vec<AlbumInfo>> {
+            self.runtime.block_on(self.inner.get_albums())
+        }
+
+        /// Создать новый альбом
+        pub fn create_album(&self, info: CreateAlbumInfo) -> Result<AlbumInfo> {
+            self.runtime.block_on(self.inner.create_album(info))
+        }
+
+        /// Удалить альбом
+        pub fn delete_album(&self, album_id: AlbumId) -> Result<()> {
+            self.runtime.block_on(self.inner.delete_album(album_id))
+        }
+
+        /// Получить данные без альбомов
+        pub fn get_data_without_albums(&self) -> Result<Vec<DataInfo>> {
+            self.runtime.block_on(self.inner.get_data_without_albums())
+        }
+
+        /// Получить данные альбома
+        pub fn get_album_data(&self, album_id: AlbumId) -> Result<Vec<DataInfo>> {
+            self.runtime.block_on(self.inner.get_album_data(album_id))
+        }
+
+        /// Загрузить файл
+        pub fn upload_file<P: AsRef<Path>>(&self, file_path: P, target: impl Into<UploadTarget>) -> Result<DataId> {
+            self.runtime.block_on(self.inner.upload_file(file_path, target))
+        }
+
+        /// Загрузить файл с возможностью отмены
+        pub fn upload_file_with<P: AsRef<Path>>(
+            &self,
+            file_path: P,
+            target: impl Into<UploadTarget>,
+            options: TransferOptions,
+        ) -> Result<DataId> {
+            self.runtime.block_on(self.inner.upload_file_with(file_path, target, options))
+        }
+
+        /// Загрузить файл из источника потоком
+        pub fn upload_source(
+            &self,
+            source: UploadSource,
+            target: impl Into<UploadTarget>,
+            options: TransferOptions,
+        ) -> Result<DataId> {
+            self.runtime.block_on(self.inner.upload_source(source, target, options))
+        }
+
+        /// Загрузить несколько файлов из источников одной формой
+        pub fn upload_sources(
+            &self,
+            sources: Vec<UploadSource>,
+            target: impl Into<UploadTarget>,
+            options: TransferOptions,
+        ) -> Result<Vec<DataId>> {
+            self.runtime.block_on(self.inner.upload_sources(sources, target, options))
+        }
+
+        /// Загрузить файлы несколькими формами в пределах бюджета планировщика
+        pub fn upload_batched(
+            &self,
+            sources: Vec<UploadSource>,
+            target: impl Into<UploadTarget>,
+            planner: &UploadBatchPlanner,
+            options: TransferOptions,
+        ) -> Result<Vec<DataId>> {
+            self.runtime.block_on(self.inner.upload_batched(sources, target, planner, options))
+        }
+
+        /// Загрузить файл из данных
+        pub fn upload_file_data(&self, data: &[u8], filename: &str, target: impl Into<UploadTarget>) -> Result<DataId> {
+            self.runtime.block_on(self.inner.upload_file_data(data, filename, target))
+        }
+
+        /// Загрузить файл из данных с возможностью отмены
+        pub fn upload_file_data_with(
+            &self,
+            data: &[u8],
+            filename: &str,
+            target: impl Into<UploadTarget>,
+            options: TransferOptions,
+        ) -> Result<DataId> {
+            self.runtime.block_on(self.inner.upload_file_data_with(data, filename, target, options))
+        }
+
+        /// Загрузить несколько файлов
+        pub fn upload_multiple_files<P: AsRef<Path>>(
+            &self,
+            file_paths: &[P],
+            target: impl Into<UploadTarget>,
+        ) -> Result<Vec<DataId>> {
+            self.runtime.block_on(self.inner.upload_multiple_files(file_paths, target))
+        }
+
+        /// Загрузить несколько файлов с возможностью отмены
+        pub fn upload_multiple_files_with<P: AsRef<Path>>(
+            &self,
+            file_paths: &[P],
+            target: impl Into<UploadTarget>,
+            options: TransferOptions,
+        ) -> Result<Vec<DataId>> {
+            self.runtime.block_on(self.inner.upload_multiple_files_with(file_paths, target, options))
+        }
+
+        /// Загрузить файлы отдельными запросами, по `concurrency` одновременно, см. [`BulkUploader`]
+        pub fn upload_bulk(
+            &self,
+            sources: Vec<UploadSource>,
+            target: impl Into<UploadTarget>,
+            concurrency: usize,
+            options: TransferOptions,
+        ) -> BulkUploadReport {
+            let uploader = BulkUploader::new(&self.inner, target)
+                .with_concurrency(concurrency)
+                .with_options(options);
+            self.runtime.block_on(uploader.upload(sources))
+        }
+
+        /// Получить превью
+        pub fn get_preview(&self, data_id: DataId) -> Result<Vec<u8>> {
+            self.runtime.block_on(self.inner.get_preview(data_id))
+        }
+
+        /// Получить превью потоком
+        pub fn stream_preview(&self, data_id: DataId) -> Result<BlockingDataStream> {
+            self.block_on_stream(self.inner.stream_preview(data_id))
+        }
+
+        /// Сохранить превью в файл
+        pub fn save_preview<P: AsRef<Path>>(&self, data_id: DataId, output_path: P) -> Result<()> {
+            self.runtime.block_on(self.inner.save_preview(data_id, output_path))
+        }
+
+        /// Получить данные файла
+        pub fn get_data(&self, data_id: DataId) -> Result<Vec<u8>> {
+            self.runtime.block_on(self.inner.get_data(data_id))
+        }
+
+        /// Получить данные файла потоком
+        pub fn stream_data(&self, data_id: DataId) -> Result<BlockingDataStream> {
+            self.block_on_stream(self.inner.stream_data(data_id))
+        }
+
+        /// Скачать файл с прогрессом
+        pub fn download_data<P: AsRef<Path>>(
+            &self,
+            data_id: DataId,
+            output_path: P,
+            progress: Option<ProgressCallback>,
+        ) -> Result<()> {
+            self.runtime.block_on(self.inner.download_data(data_id, output_path, progress))
+        }
+
+        /// Скачать файл с отменой и прогрессом
+        pub fn download_data_with<P: AsRef<Path>>(
+            &self,
+            data_id: DataId,
+            output_path: P,
+            options: TransferOptions,
+        ) -> Result<()> {
+            self.runtime.block_on(self.inner.download_data_with(data_id, output_path, options))
+        }
+
+        /// Скачать файл с докачкой
+        pub fn download_data_resumable<P: AsRef<Path>>(
+            &self,
+            info: &DataInfo,
+            output_path: P,
+            options: TransferOptions,
+        ) -> Result<()> {
+            self.runtime.block_on(self.inner.download_data_resumable(info, output_path, options))
+        }
+
+        /// Скачать видео несколькими параллельными запросами диапазонов
+        pub fn download_data_parallel<P: AsRef<Path>>(
+            &self,
+            info: &DataInfo,
+            output_path: P,
+            segments: usize,
+            options: TransferOptions,
+        ) -> Result<()> {
+            self.runtime
+                .block_on(self.inner.download_data_parallel(info, output_path, segments, options))
+        }
+
+        /// Получить видео поток с поддержкой Range
+        pub fn get_video_stream(
+            &self,
+            data_id: DataId,
+            range_start: Option<u64>,
+            range_end: Option<u64>,
+        ) -> Result<(Vec<u8>, VideoHeaders)> {
+            self.runtime.block_on(self.inner.get_video_stream(data_id, range_start, range_end))
+        }
+
+        /// Получить видео потоком с поддержкой Range; статус 206 для диапазона
+        pub fn stream_video(
+            &self,
+            data_id: DataId,
+            range_start: Option<u64>,
+            range_end: Option<u64>,
+        ) -> Result<BlockingDataStream> {
+            self.block_on_stream(self.inner.stream_video(data_id, range_start, range_end))
+        }
+
+        /// Открыть файл записи для чтения с произвольным доступом, см. [`BlockingRemoteMedia`]
+        pub fn open_media(&self, data_id: DataId, options: RemoteMediaOptions) -> Result<BlockingRemoteMedia> {
+            let inner = self.runtime.block_on(self.inner.open_media(data_id, options))?;
+            Ok(BlockingRemoteMedia {
+                inner,
+                runtime: self.runtime.clone(),
+            })
+        }
+
+        /// Удалить файл
+        pub fn delete_data(&self, data_id: DataId) -> Result<()> {
+            self.runtime.block_on(self.inner.delete_data(data_id))
+        }
+
+        /// Дождаться ответа и отдать тело читателю на рантайме клиента
+        fn block_on_stream(
+            &self,
+            request: impl std::future::Future<Output = Result<DataStream>>,
+        ) -> Result<BlockingDataStream> {
+            Ok(BlockingDataStream {
+                inner: self.runtime.block_on(request)?,
+                runtime: self.runtime.clone(),
+                chunk: Bytes::new(),
+            })
+        }
     }
-    
-    /// Сохранить превью в файл
-    pub async fn save_preview<P: AsRef<Path>>(
-        &self,
-        data_id: i64,
-        output_path: P,
-    ) -> Result<()> {
-        let data = self.get_preview(data_id).await?;
-        let mut file = File::create(output_path).await?;
-        file.write_all(&data).await?;
-        Ok(())
+
+    /// Синхронный [`DataStream`]: тело ответа как `std::io::Read`.
+    ///
+    /// Порции читаются на рантайме клиента по мере вызовов `read`; таймаут чтения
+    /// клиента действует на каждую порцию.
+    pub struct BlockingDataStream {
+        inner: DataStream,
+        runtime: Arc<Runtime>,
+        /// Непрочитанный остаток текущей порции
+        chunk: Bytes,
     }
-    
-    /// Получить данные файла
-    pub async fn get_data(&self, data_id: i64) -> Result<Vec<u8>> {
-        let url = format!("{}/api/data/{}", self.base_url, data_id);
-        let response = self.client
-            .get(&url)
-            .headers(self.create_headers())
-            .send()
-            .await?;
-            
-        match response.status() {
-            StatusCode::OK => Ok(response.bytes().await?.to_vec()),
-            StatusCode::UNAUTHORIZED => Err(Error::Unauthorized),
-            StatusCode::NOT_FOUND => Err(Error::NotFound(format!("Data {} not found", data_id))),
-            status => {
-                let message = response.text().await.unwrap_or_default();
-                Err(Error::Api {
-                    status: status.as_u16(),
-                    message,
-                })
-            }
+
+    impl std::fmt::Debug for BlockingDataStream {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            f.debug_struct("BlockingDataStream")
+                .field("inner", &self.inner)
+                .finish_non_exhaustive()
         }
     }
-    
-    /// Скачать файл с прогрессом
-    pub async fn download_data<P: AsRef<Path>>(
-        &self,
-        data_id: i64,
-        output_path: P,
-        progress: Option<ProgressCallback>,
-    ) -> Result<()> {
-        let url = format!("{}/api/data/{}", self.base_url, data_id);
-        let response = self.client
-            .get(&url)
-            .headers(self.create_headers())
-            .send()
-            .await?;
-            
-        match response.status() {
-            StatusCode::OK => {
-                let total_size = response
-                    .headers()
-                    .get("content-length")
-                    .and_then(|ct_len| ct_len.to_str().ok())
-                    .and_then(|ct_len| ct_len.parse::<u64>().ok())
-                    .unwrap_or(0);
-                    
-                let mut file = File::create(output_path).await?;
-                let mut downloaded = 0u64;
-                let mut stream = response.bytes_stream();
-                
-                use futures_util::StreamExt;
-                while let Some(chunk) = stream.next().await {
-                    let chunk = chunk?;
-                    file.write_all(&chunk).await?;
-                    downloaded += chunk.len() as u64;
-                    
-                    if let Some(ref callback) = progress {
-                        callback(downloaded, total_size);
-                    }
-                }
-                
-                Ok(())
+
+    impl BlockingDataStream {
+        /// Статус ответа: 200 или 206 для диапазона видео
+        pub fn status(&self) -> StatusCode {
+            self.inner.status()
+        }
+
+        /// Заголовки ответа
+        pub fn headers(&self) -> &HeaderMap {
+            self.inner.headers()
+        }
+
+        /// Длина тела из `Content-Length`
+        pub fn content_length(&self) -> Option<u64> {
+            self.inner.content_length()
+        }
+
+        /// `Content-Type` ответа
+        pub fn content_type(&self) -> Option<&str> {
+            self.inner.content_type()
+        }
+
+        /// `Content-Range` ответа на запрос диапазона
+        pub fn content_range(&self) -> Option<&str> {
+            self.inner.content_range()
+        }
+    }
+
+    impl std::io::Read for BlockingDataStream {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            if buf.is_empty() {
+                return Ok(0);
             }
-            StatusCode::UNAUTHORIZED => Err(Error::Unauthorized),
-            StatusCode::NOT_FOUND => Err(Error::NotFound(format!("Data {} not found", data_id))),
-            status => {
-                let message = response.text().await.unwrap_or_default();
-                Err(Error::Api {
-                    status: status.as_u16(),
-                    message,
-                })
+            while self.chunk.is_empty() {
+                match self.runtime.block_on(self.inner.next()) {
+                    Some(chunk) => self.chunk = chunk.map_err(io_error)?,
+                    None => return Ok(0),
+                }
             }
+            let count = buf.len().min(self.chunk.len());
+            buf[..count].copy_from_slice(&self.chunk.split_to(count));
+            Ok(count)
         }
     }
-    
-    /// Получить видео поток с поддержкой Range
-    pub async fn get_video_stream(
-        &self,
-        data_id: i64,
-        range_start: Option<u64>,
-        range_end: Option<u64>,
-    ) -> Result<(Vec<u8>, VideoHeaders)> {
-        let url = format!("{}/api/data/{}", self.base_url, data_id);
-        let mut headers = self.create_headers();
-        
-        if range_start.is_some() || range_end.is_some() {
-            let range_value = match (range_start, range_end) {
-                (Some(start), Some(end)) => format!("bytes={}-{}", start, end),
-                (Some(start), None) => format!("bytes={}-", start),
-                (None, Some(end)) => format!("bytes=-{}", end),
-                _ => unreachable!(),
-            };
-            headers.insert(RANGE, HeaderValue::from_str(&range_value).unwrap());
+
+    /// Синхронный [`RemoteMedia`]: `std::io::Read` + `std::io::Seek`.
+    ///
+    /// Работает на рантайме клиента, который разделяет с ним. Рантайм однопоточный,
+    /// поэтому блоки упреждения загружаются, пока идет вызов `read`: пока ждется
+    /// текущий блок, следующие запрашиваются параллельно с ним.
+    pub struct BlockingRemoteMedia {
+        inner: RemoteMedia,
+        runtime: Arc<Runtime>,
+    }
+
+    impl std::fmt::Debug for BlockingRemoteMedia {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            f.debug_struct("BlockingRemoteMedia")
+                .field("inner", &self.inner)
+                .finish_non_exhaustive()
         }
-        
-        let response = self.client
-            .get(&url)
-            .headers(headers)
-            .send()
-            .await?;
-            
-        let status = response.status();
-        if status != StatusCode::OK && status != StatusCode::PARTIAL_CONTENT {
-            let message = response.text().await.unwrap_or_default();
-            return Err(Error::Api {
-                status: status.as_u16(),
-                message,
-            });
+    }
+
+    impl BlockingRemoteMedia {
+        /// Идентификатор записи
+        pub fn data_id(&self) -> DataId {
+            self.inner.data_id()
+        }
+
+        /// Размер файла, байт
+        pub fn len(&self) -> u64 {
+            self.inner.len()
+        }
+
+        /// Файл пуст
+        pub fn is_empty(&self) -> bool {
+            self.inner.is_empty()
+        }
+
+        /// `Content-Type` ответа сервера
+        pub fn content_type(&self) -> Option<&str> {
+            self.inner.content_type()
+        }
+
+        /// Текущая позиция чтения
+        pub fn position(&self) -> u64 {
+            self.inner.position()
         }
-        
-        let video_headers = VideoHeaders {
-            content_range: response
-                .headers()
-                .get("content-range")
-                .and_then(|h| h.to_str().ok())
-                .map(|s| s.to_string()),
-            content_length: response
-                .headers()
-                .get("content-length")
-                .and_then(|h| h.to_str().ok())
-                .map(|s| s.to_string()),
-            content_type: response
-                .headers()
-                .get("content-type")
-                .and_then(|h| h.to_str().ok())
-                .map(|s| s.to_string()),
-        };
-        
-        let data = response.bytes().await?.to_vec();
-        Ok((data, video_headers))
     }
-    
-    /// Удалить файл
-    pub async fn delete_data(&self, data_id: i64) -> Result<()> {
-        let url = format!("{}/api/data/{}", self.base_url, data_id);
-        let response = self.client
-            .delete(&url)
-            .headers(self.create_headers())
-            .send()
-            .await?;
-            
-        match response.status() {
-            StatusCode::OK => Ok(()),
-            StatusCode::UNAUTHORIZED => Err(Error::Unauthorized),
-            status => {
-                let message = response.text().await.unwrap_or_default();
-                Err(Error::Api {
-                    status: status.as_u16(),
-                    message,
-                })
-            }
+
+    impl std::io::Read for BlockingRemoteMedia {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            self.runtime.block_on(self.inner.read(buf))
+        }
+    }
+
+    impl std::io::Seek for BlockingRemoteMedia {
+        fn seek(&mut self, position: std::io::SeekFrom) -> std::io::Result<u64> {
+            self.runtime.block_on(self.inner.seek(position))
+        }
+    }
+
+    impl ZeroGalleryClientBuilder {
+        /// Создать синхронный клиент
+        pub fn build_blocking(self) -> Result<ZeroGalleryBlockingClient> {
+            ZeroGalleryBlockingClient::from_async(self.build()?)
         }
     }
 }
 
-/// Заголовки видео ответа
-#[derive(Debug, Clone)]
-pub struct VideoHeaders {
-    pub content_range: Option<String>,
-    pub content_length: Option<String>,
-    pub content_type: Option<String>,
+#[cfg(feature = "blocking")]
+pub use blocking::{BlockingDataStream, BlockingRemoteMedia, ZeroGalleryBlockingClient};
+
+/// Индикаторы прогресса в терминале на indicatif
+#[cfg(feature = "progress")]
+pub mod progress_bars {
+    use super::{FileProgress, TransferOptions};
+    use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
+    use std::collections::HashMap;
+    use std::sync::{Arc, Mutex};
+
+    const TOTAL_TEMPLATE: &str = "{msg} [{bar:40}] {bytes}/{total_bytes} {bytes_per_sec} {eta}";
+    const FILE_TEMPLATE: &str = "  {msg} [{bar:30}] {bytes}/{total_bytes}";
+    const UNKNOWN_TEMPLATE: &str = "{spinner} {msg} {bytes} {bytes_per_sec}";
+
+    fn style(template: &str) -> ProgressStyle {
+        ProgressStyle::with_template(template)
+            .expect("progress template is valid")
+            .progress_chars("=> ")
+    }
+
+    /// Индикаторы передачи: общий и, при [`TransferProgressBars::with_file_bars`],
+    /// по одному на каждый файл загрузки. Клоны показывают одни индикаторы.
+    #[derive(Clone)]
+    pub struct TransferProgressBars {
+        multi: MultiProgress,
+        total: ProgressBar,
+        files: Arc<Mutex<HashMap<usize, ProgressBar>>>,
+        file_bars: bool,
+    }
+
+    impl std::fmt::Debug for TransferProgressBars {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            f.debug_struct("TransferProgressBars")
+                .field("file_bars", &self.file_bars)
+                .finish_non_exhaustive()
+        }
+    }
+
+    impl TransferProgressBars {
+        /// Индикаторы в stderr с подписью общего индикатора
+        pub fn new(message: impl Into<String>) -> Self {
+            Self::with_multi_progress(MultiProgress::new(), message)
+        }
+
+        /// Индикаторы в существующем `MultiProgress`
+        pub fn with_multi_progress(multi: MultiProgress, message: impl Into<String>) -> Self {
+            let total = multi.add(ProgressBar::no_length());
+            total.set_style(style(UNKNOWN_TEMPLATE));
+            total.set_message(message.into());
+            Self {
+                multi,
+                total,
+                files: Arc::new(Mutex::new(HashMap::new())),
+                file_bars: false,
+            }
+        }
+
+        /// Показывать отдельный индикатор на каждый файл загрузки
+        pub fn with_file_bars(mut self, enabled: bool) -> Self {
+            self.file_bars = enabled;
+            self
+        }
+
+        /// Общий индикатор
+        pub fn total(&self) -> &ProgressBar {
+            &self.total
+        }
+
+        /// Подключить индикаторы к параметрам передачи
+        pub fn attach(&self, options: TransferOptions) -> TransferOptions {
+            let bars = self.clone();
+            let options = options.with_progress(Box::new(move |done, total| bars.on_total(done, total)));
+            if !self.file_bars {
+                return options;
+            }
+            let bars = self.clone();
+            options.with_file_progress(Box::new(move |progress| bars.on_file(progress)))
+        }
+
+        /// Завершить все индикаторы, оставив их на экране
+        pub fn finish(&self) {
+            for bar in self.lock().values() {
+                bar.finish();
+            }
+            self.total.finish();
+        }
+
+        fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<usize, ProgressBar>> {
+            self.files.lock().unwrap_or_else(|e| e.into_inner())
+        }
+
+        fn on_total(&self, done: u64, total: u64) {
+            if total > 0 && self.total.length() != Some(total) {
+                self.total.set_length(total);
+                self.total.set_style(style(TOTAL_TEMPLATE));
+            }
+            self.total.set_position(done);
+        }
+
+        fn on_file(&self, progress: FileProgress<'_>) {
+            let mut files = self.lock();
+            let bar = files.entry(progress.index).or_insert_with(|| {
+                let bar = self.multi.insert_before(&self.total, ProgressBar::no_length());
+                bar.set_style(style(UNKNOWN_TEMPLATE));
+                bar.set_message(progress.file_name.to_string());
+                bar
+            });
+            if let Some(total) = progress.total {
+                if bar.length() != Some(total) {
+                    bar.set_length(total);
+                    bar.set_style(style(FILE_TEMPLATE));
+                }
+            }
+            bar.set_position(progress.sent);
+        }
+    }
 }
 
+#[cfg(feature = "progress")]
+pub use progress_bars::TransferProgressBars;
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -567,8 +5987,8 @@ mod tests {
         
         for (size, expected) in test_cases {
             let data = DataInfo {
-                id: 1,
-                album_id: 1,
+                id: DataId(1),
+                album_id: Some(AlbumId(1)),
                 size,
                 created_timestamp: 0,
                 name: String::new(),
@@ -576,6 +5996,7 @@ mod tests {
                 description: String::new(),
                 mime_type: String::new(),
                 tags: String::new(),
+                has_preview: false,
             };
             
             assert_eq!(data.format_size(), expected);
@@ -585,8 +6006,8 @@ mod tests {
     #[test]
     fn test_created_time() {
         let data = DataInfo {
-            id: 1,
-            album_id: 1,
+            id: DataId(1),
+            album_id: Some(AlbumId(1)),
             size: 0,
             created_timestamp: 1640995200000, // 2022-01-01 00:00:00 UTC
             name: String::new(),
@@ -594,10 +6015,280 @@ mod tests {
             description: String::new(),
             mime_type: String::new(),
             tags: String::new(),
+            has_preview: false,
         };
         
         let time = data.created_time();
         let duration = time.duration_since(UNIX_EPOCH).unwrap();
         assert_eq!(duration.as_secs(), 1640995200);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_ids_keep_server_format() {
+        let json = r#"{"id":5,"albumId":-1,"size":3,"createdTimestamp":0,"name":"a.txt",
+            "extension":".txt","description":"","mimeType":"text/plain","tags":""}"#;
+        let data: DataInfo = serde_json::from_str(json).unwrap();
+        assert_eq!(data.id, DataId(5));
+        assert_eq!(data.album_id, None);
+        let value = serde_json::to_value(&data).unwrap();
+        assert_eq!(value["id"], 5);
+        assert_eq!(value["albumId"], -1);
+
+        let album: AlbumInfo = serde_json::from_str(
+            r#"{"id":2,"imagePreviewId":7,"name":"trip","description":"","isProtected":false}"#,
+        )
+        .unwrap();
+        assert_eq!(album.id, AlbumId(2));
+        assert_eq!(album.image_preview_id, Some(DataId(7)));
+        assert_eq!(UploadTarget::from(album.id), UploadTarget::Album(AlbumId(2)));
+        assert_eq!(UploadTarget::from(data.album_id), UploadTarget::Public);
+    }
+
+    #[test]
+    fn test_data_info_tags_and_kind() {
+        let mut data: DataInfo = serde_json::from_str(
+            r#"{"id":3,"albumId":1,"name":"IMG_1.HEIC","extension":".HEIC","description":null,
+                "tags":"sea; trip;;sea","hasPreview":true,"conversionState":2}"#,
+        )
+        .unwrap();
+        assert_eq!(data.description, "");
+        assert!(data.has_preview);
+        assert_eq!(data.kind(), DataKind::Image);
+        let tags: Vec<String> = data.tags().iter().map(Tag::to_string).collect();
+        assert_eq!(tags, ["sea", "trip"]);
+
+        data.set_tags(Tag::new("beach"));
+        assert_eq!(data.tags, "beach");
+        assert_eq!(Tag::new(" a;b "), None);
+
+        let video = DataInfo {
+            extension: ".MP4".to_string(),
+            ..Default::default()
+        };
+        assert_eq!(video.kind(), DataKind::Video);
+        // Сервер определяет тип только по расширению
+        let mime_only = DataInfo {
+            mime_type: "video/mp4".to_string(),
+            ..Default::default()
+        };
+        assert_eq!(mime_only.kind(), DataKind::Binary);
+        assert_eq!(DataInfo::default().kind(), DataKind::Binary);
+    }
+
+    #[test]
+    fn test_album_credentials_shared_between_clones() {
+        let registry = AlbumCredentials::new();
+        let clone = registry.clone();
+        clone.insert(AlbumId(7), "album-7");
+        clone.remember_data(DataId(42), AlbumId(7));
+
+        assert_eq!(registry.token(AlbumId(7)).as_deref(), Some("album-7"));
+        assert_eq!(registry.album_of(DataId(42)), Some(AlbumId(7)));
+        assert!(!format!("{:?}", registry).contains("album-7"));
+
+        registry.forget_data(DataId(42));
+        assert_eq!(clone.album_of(DataId(42)), None);
+    }
+
+    const PROFILES: &str = r#"
+        [profiles.prod]
+        url = "https://gallery.example.com"
+        upload_token = "upload"
+
+        [profiles.prod.albums]
+        12 = "album-12"
+    "#;
+
+    #[test]
+    fn test_profile_from_toml() {
+        let profile = Profile::from_toml(PROFILES, "prod").unwrap();
+        assert_eq!(profile.url.as_deref(), Some("https://gallery.example.com"));
+        assert_eq!(profile.credentials().upload_token.as_deref(), Some("upload"));
+        let albums = profile.album_credentials().unwrap();
+        assert_eq!(albums.token(AlbumId(12)).as_deref(), Some("album-12"));
+
+        assert!(matches!(Profile::from_toml(PROFILES, "dev"), Err(Error::Profile(_))));
+    }
+
+    #[test]
+    fn test_profile_env_overrides() {
+        let profile = Profile::from_toml(PROFILES, "prod").unwrap();
+        let env: HashMap<&str, &str> = [
+            (ENV_URL, "http://localhost:8081"),
+            (ENV_ACCESS_TOKEN, "access"),
+            (ENV_UPLOAD_TOKEN, ""),
+        ]
+        .into_iter()
+        .collect();
+        let resolved =
+            Profile::resolve("prod", Some(profile), None, |key| env.get(key).map(|v| v.to_string()))
+                .unwrap();
+        assert_eq!(resolved.url.as_deref(), Some("http://localhost:8081"));
+        assert_eq!(resolved.access_token.as_deref(), Some("access"));
+        // Пустая переменная не затирает значение из файла
+        assert_eq!(resolved.upload_token.as_deref(), Some("upload"));
+
+        let missing = Profile::resolve("dev", None, None, |_| None);
+        assert!(matches!(missing, Err(Error::Profile(message)) if message.contains("'dev' not found")));
+    }
+
+    #[test]
+    fn test_profile_malformed_entries() {
+        let typo = "[profiles.prod]\nurl = \"http://x\"\naccess_tokn = \"a\"\n";
+        assert!(matches!(Profile::from_toml(typo, "prod"), Err(Error::Profile(_))));
+
+        let bad_album = "[profiles.prod]\nurl = \"http://x\"\n[profiles.prod.albums]\nholiday = \"t\"\n";
+        let profile = Profile::from_toml(bad_album, "prod").unwrap();
+        assert!(matches!(profile.album_credentials(), Err(Error::Profile(message)) if message.contains("holiday")));
+    }
+
+    #[tokio::test]
+    async fn test_multipart_encoding() {
+        let form = Multipart::new()
+            .file("files", "a.txt", &b"first"[..])
+            .file("files", "фото \"1\".jpg", &b"second"[..]);
+        let boundary = form.boundary.clone();
+        let (content_type, body) = form.into_body();
+        assert_eq!(
+            content_type.to_str().unwrap(),
+            format!("multipart/form-data; boundary={}", boundary)
+        );
+
+        let length = body.length().unwrap();
+        let RequestBody::Stream { stream, .. } = body else {
+            panic!("multipart body must be a stream");
+        };
+        let chunks: Vec<Bytes> = stream.map(|chunk| chunk.unwrap()).collect().await;
+        let encoded = String::from_utf8(chunks.concat()).unwrap();
+        assert_eq!(encoded.len() as u64, length);
+        assert!(encoded.starts_with(&format!(
+            "--{}\r\nContent-Disposition: form-data; name=\"files\"; filename=\"a.txt\"\r\n",
+            boundary
+        )));
+        assert!(encoded.contains("filename*=UTF-8''%D1%84%D0%BE%D1%82%D0%BE%20%221%22.jpg"));
+        assert!(encoded.contains("\r\n\r\nsecond\r\n"));
+        assert!(encoded.ends_with(&format!("--{}--\r\n", boundary)));
+    }
+
+    #[tokio::test]
+    async fn test_upload_source_reader_is_single_use() {
+        let source = UploadSource::from_reader("a.bin", &b"abc"[..], None);
+        let form = Multipart::new().source("file", &source, None).unwrap();
+        let (_, body) = form.into_body();
+        assert_eq!(body.length(), None);
+        assert!(matches!(Multipart::new().source("file", &source, None), Err(Error::Config(_))));
+
+        let source = UploadSource::from_bytes("b.bin", &b"abc"[..]);
+        for _ in 0..2 {
+            let (_, body) = Multipart::new().source("file", &source, None).unwrap().into_body();
+            assert!(body.length().is_some());
+        }
+    }
+
+    #[test]
+    fn test_parse_content_range() {
+        assert_eq!(parse_content_range("bytes 100-199/1000"), Some((100, 199, Some(1000))));
+        assert_eq!(parse_content_range("bytes 0-0/*"), Some((0, 0, None)));
+        assert_eq!(parse_content_range("bytes 5-4/10"), None);
+        assert_eq!(parse_content_range("items 0-1/2"), None);
+        assert_eq!(parse_content_range("bytes */1000"), None);
+    }
+
+    #[test]
+    fn test_segment_ranges() {
+        let mib = PARALLEL_SEGMENT_MIN;
+        assert_eq!(segment_ranges(12 * mib, 4), vec![
+            (0, 3 * mib - 1),
+            (3 * mib, 6 * mib - 1),
+            (6 * mib, 9 * mib - 1),
+            (9 * mib, 12 * mib - 1),
+        ]);
+        assert_eq!(segment_ranges(3 * mib + 2, 3).last(), Some(&(2 * mib + 2, 3 * mib + 1)));
+        // Не меньше мебибайта на диапазон
+        assert_eq!(segment_ranges(2 * mib + 1, 8).len(), 2);
+        assert_eq!(segment_ranges(mib / 2, 8), vec![(0, mib / 2 - 1)]);
+        assert!(segment_ranges(0, 4).is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_partial_download_prepare() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let output = temp_dir.path().join("video.mp4");
+        let (part, meta) = PartialDownload::paths(&output);
+        assert_eq!(part, temp_dir.path().join("video.mp4.part"));
+        assert_eq!(meta, temp_dir.path().join("video.mp4.part.meta"));
+
+        let download = PartialDownload { data_id: DataId(4), size: 10 };
+        assert_eq!(download.prepare(&part, &meta).await.unwrap(), 0);
+        std::fs::write(&part, b"abcd").unwrap();
+        assert_eq!(download.prepare(&part, &meta).await.unwrap(), 4);
+
+        // Другой размер записи: начать заново
+        let changed = PartialDownload { data_id: DataId(4), size: 12 };
+        assert_eq!(changed.prepare(&part, &meta).await.unwrap(), 0);
+        assert_eq!(std::fs::metadata(&part).unwrap().len(), 0);
+    }
+
+    #[test]
+    fn test_upload_batch_plan() {
+        let sources = vec![
+            UploadSource::from_bytes("a", vec![0u8; 4]),
+            UploadSource::from_bytes("b", vec![0u8; 4]),
+            UploadSource::from_bytes("c", vec![0u8; 4]),
+            UploadSource::from_bytes("big", vec![0u8; 20]),
+            UploadSource::from_bytes("d", vec![0u8; 1]),
+            UploadSource::from_reader("stream", &b"xyz"[..], None),
+            UploadSource::from_bytes("e", vec![0u8; 1]),
+            UploadSource::from_bytes("f", vec![0u8; 1]),
+            UploadSource::from_bytes("g", vec![0u8; 1]),
+        ];
+        let planner = UploadBatchPlanner::new().with_max_bytes(8).with_max_files(2);
+        assert_eq!(planner.plan(&sources), vec![0..2, 2..3, 3..4, 4..5, 5..6, 6..8, 8..9]);
+        assert!(planner.plan(&[]).is_empty());
+    }
+
+    #[test]
+    fn test_token_bucket_reserve() {
+        let start = Instant::now();
+        let mut bucket = TokenBucket {
+            rate: 10.0,
+            capacity: 10.0,
+            tokens: 10.0,
+            updated: start,
+        };
+        assert_eq!(bucket.reserve(10.0, start), Duration::ZERO);
+        // Сверх запаса: долг ждет пополнения
+        assert_eq!(bucket.reserve(5.0, start), Duration::from_millis(500));
+        // За секунду долг погашен, запас не превышает емкости
+        assert_eq!(bucket.reserve(5.0, start + Duration::from_secs(1)), Duration::ZERO);
+        assert_eq!(bucket.reserve(10.0, start + Duration::from_secs(10)), Duration::ZERO);
+    }
+
+    #[test]
+    fn test_traffic_class_of_operation() {
+        assert_eq!(TrafficClass::of("upload_multiple_files"), TrafficClass::Upload);
+        assert_eq!(TrafficClass::of("download_data"), TrafficClass::Download);
+        assert_eq!(TrafficClass::of("get_video_stream"), TrafficClass::Download);
+        assert_eq!(TrafficClass::of("get_album_data"), TrafficClass::Listing);
+    }
+
+    #[test]
+    fn test_metrics_prometheus_histogram() {
+        let mut metrics = OperationMetrics {
+            requests: 1,
+            statuses: BTreeMap::from([(200, 1)]),
+            ..Default::default()
+        };
+        metrics.latency.observe(0.02);
+        metrics.latency.observe(100.0);
+        let snapshot = MetricsSnapshot {
+            operations: BTreeMap::from([("get_version", metrics)]),
+        };
+        let text = snapshot.to_prometheus();
+        assert!(text.contains("zerogallery_request_duration_seconds_bucket{operation=\"get_version\",le=\"0.01\"} 0\n"));
+        assert!(text.contains("zerogallery_request_duration_seconds_bucket{operation=\"get_version\",le=\"0.025\"} 1\n"));
+        assert!(text.contains("zerogallery_request_duration_seconds_bucket{operation=\"get_version\",le=\"30\"} 1\n"));
+        assert!(text.contains("zerogallery_request_duration_seconds_bucket{operation=\"get_version\",le=\"+Inf\"} 2\n"));
+        assert!(text.contains("zerogallery_request_duration_seconds_sum{operation=\"get_version\"} 100.02\n"));
+    }
+}
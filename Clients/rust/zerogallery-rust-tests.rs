@@ -1,22 +1,37 @@
 // tests/integration_tests.rs
-use mockito::{mock, Mock, Server};
-use zerogallery::{AlbumInfo, CreateAlbumInfo, DataInfo, ZeroGalleryClient};
+use mockito::{Matcher, Server};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use zerogallery::{
+    AlbumCredentials, AlbumId, ApiRequest, ApiResponse, BulkUploader, CancellationToken, CircuitBreakerPolicy,
+    CircuitState, ClientMetrics, CreateAlbumInfo, Credentials, DataId, DataInfo, Endpoint, Error, ErrorClass,
+    PartialFile, Profile, RateLimit, RateLimiter, RemoteMediaOptions, RetryPolicy, TrafficClass, TransferOptions,
+    Transport, UploadBatchPlanner, UploadSource, UploadTarget, ZeroGalleryClient, ZeroGalleryClientBuilder,
+};
 
 fn create_test_client(server_url: &str) -> ZeroGalleryClient {
-    ZeroGalleryClient::with_token(server_url, Some("test-token".to_string()))
+    ZeroGalleryClient::with_credentials(
+        server_url,
+        Credentials::new()
+            .with_upload_token("upload-token")
+            .with_access_token("test-token"),
+    )
 }
 
 #[tokio::test]
 async fn test_get_version() {
-    let mut server = Server::new();
+    let mut server = Server::new_async().await;
     let url = server.url();
     
     let _m = server
         .mock("GET", "/api/version")
-        .match_header("X-Access-Token", "test-token")
+        .match_header("X-ZERO-UPLOAD-TOKEN", Matcher::Missing)
+        .match_header("X-ZERO-ACCESS-TOKEN", Matcher::Missing)
         .with_status(200)
         .with_body("1.0.0")
-        .create();
+        .create_async()
+        .await;
     
     let client = create_test_client(&url);
     let version = client.get_version().await.unwrap();
@@ -25,7 +40,7 @@ async fn test_get_version() {
 
 #[tokio::test]
 async fn test_get_albums() {
-    let mut server = Server::new();
+    let mut server = Server::new_async().await;
     let url = server.url();
     
     let albums_json = r#"[
@@ -50,7 +65,8 @@ async fn test_get_albums() {
         .with_status(200)
         .with_header("content-type", "application/json")
         .with_body(albums_json)
-        .create();
+        .create_async()
+        .await;
     
     let client = create_test_client(&url);
     let albums = client.get_albums().await.unwrap();
@@ -64,7 +80,7 @@ async fn test_get_albums() {
 
 #[tokio::test]
 async fn test_create_album() {
-    let mut server = Server::new();
+    let mut server = Server::new_async().await;
     let url = server.url();
     
     let response_json = r#"{
@@ -81,7 +97,8 @@ async fn test_create_album() {
         .with_status(200)
         .with_header("content-type", "application/json")
         .with_body(response_json)
-        .create();
+        .create_async()
+        .await;
     
     let client = create_test_client(&url);
     let album = client
@@ -94,35 +111,37 @@ async fn test_create_album() {
         .await
         .unwrap();
     
-    assert_eq!(album.id, 3);
+    assert_eq!(album.id, AlbumId(3));
     assert_eq!(album.name, "New Album");
     assert!(album.is_protected);
 }
 
 #[tokio::test]
 async fn test_upload_file_data() {
-    let mut server = Server::new();
+    let mut server = Server::new_async().await;
     let url = server.url();
     
     let _m = server
         .mock("POST", "/api/upload/1")
-        .match_header("X-Access-Token", "test-token")
+        .match_header("X-ZERO-UPLOAD-TOKEN", "upload-token")
+        .match_header("X-ZERO-ACCESS-TOKEN", "test-token")
         .with_status(200)
         .with_body("123")
-        .create();
+        .create_async()
+        .await;
     
     let client = create_test_client(&url);
     let file_id = client
-        .upload_file_data(b"test content", "test.txt", 1)
+        .upload_file_data(b"test content", "test.txt", AlbumId(1))
         .await
         .unwrap();
     
-    assert_eq!(file_id, 123);
+    assert_eq!(file_id, DataId(123));
 }
 
 #[tokio::test]
 async fn test_get_album_data() {
-    let mut server = Server::new();
+    let mut server = Server::new_async().await;
     let url = server.url();
     
     let data_json = r#"[
@@ -144,10 +163,11 @@ async fn test_get_album_data() {
         .with_status(200)
         .with_header("content-type", "application/json")
         .with_body(data_json)
-        .create();
+        .create_async()
+        .await;
     
     let client = create_test_client(&url);
-    let data = client.get_album_data(1).await.unwrap();
+    let data = client.get_album_data(AlbumId(1)).await.unwrap();
     
     assert_eq!(data.len(), 1);
     assert_eq!(data[0].name, "file1.txt");
@@ -156,7 +176,7 @@ async fn test_get_album_data() {
 
 #[tokio::test]
 async fn test_download_data() {
-    let mut server = Server::new();
+    let mut server = Server::new_async().await;
     let url = server.url();
     
     let _m = server
@@ -164,14 +184,15 @@ async fn test_download_data() {
         .with_status(200)
         .with_header("content-length", "12")
         .with_body("test content")
-        .create();
+        .create_async()
+        .await;
     
     let client = create_test_client(&url);
     
     let temp_dir = tempfile::tempdir().unwrap();
     let output_path = temp_dir.path().join("downloaded.txt");
     
-    client.download_data(1, &output_path, None).await.unwrap();
+    client.download_data(DataId(1), &output_path, None).await.unwrap();
     
     let content = tokio::fs::read_to_string(&output_path).await.unwrap();
     assert_eq!(content, "test content");
@@ -179,7 +200,7 @@ async fn test_download_data() {
 
 #[tokio::test]
 async fn test_download_with_progress() {
-    let mut server = Server::new();
+    let mut server = Server::new_async().await;
     let url = server.url();
     
     let _m = server
@@ -187,31 +208,34 @@ async fn test_download_with_progress() {
         .with_status(200)
         .with_header("content-length", "100")
         .with_body(vec![0u8; 100])
-        .create();
+        .create_async()
+        .await;
     
     let client = create_test_client(&url);
     
     let temp_dir = tempfile::tempdir().unwrap();
     let output_path = temp_dir.path().join("downloaded.bin");
     
-    let mut progress_called = false;
-    let progress = Box::new(|current: u64, total: u64| {
-        progress_called = true;
+    let progress_called = Arc::new(AtomicBool::new(false));
+    let called = progress_called.clone();
+    let progress = Box::new(move |current: u64, total: u64| {
+        called.store(true, Ordering::SeqCst);
         assert!(current <= total);
     });
     
     client
-        .download_data(1, &output_path, Some(progress))
+        .download_data(DataId(1), &output_path, Some(progress))
         .await
         .unwrap();
     
     let content = tokio::fs::read(&output_path).await.unwrap();
     assert_eq!(content.len(), 100);
+    assert!(progress_called.load(Ordering::SeqCst));
 }
 
 #[tokio::test]
 async fn test_video_stream_with_range() {
-    let mut server = Server::new();
+    let mut server = Server::new_async().await;
     let url = server.url();
     
     let _m = server
@@ -222,12 +246,13 @@ async fn test_video_stream_with_range() {
         .with_header("content-length", "1024")
         .with_header("content-type", "video/mp4")
         .with_body(vec![0u8; 1024])
-        .create();
+        .create_async()
+        .await;
     
     let client = create_test_client(&url);
     
     let (data, headers) = client
-        .get_video_stream(1, Some(0), Some(1023))
+        .get_video_stream(DataId(1), Some(0), Some(1023))
         .await
         .unwrap();
     
@@ -238,74 +263,142 @@ async fn test_video_stream_with_range() {
 
 #[tokio::test]
 async fn test_delete_data() {
-    let mut server = Server::new();
+    let mut server = Server::new_async().await;
     let url = server.url();
     
     let _m = server
         .mock("DELETE", "/api/data/1")
         .with_status(200)
-        .create();
+        .create_async()
+        .await;
     
     let client = create_test_client(&url);
-    client.delete_data(1).await.unwrap();
+    client.delete_data(DataId(1)).await.unwrap();
 }
 
 #[tokio::test]
 async fn test_delete_album() {
-    let mut server = Server::new();
+    let mut server = Server::new_async().await;
     let url = server.url();
     
     let _m = server
         .mock("DELETE", "/api/album/1")
         .with_status(200)
-        .create();
+        .create_async()
+        .await;
     
     let client = create_test_client(&url);
-    client.delete_album(1).await.unwrap();
+    client.delete_album(AlbumId(1)).await.unwrap();
 }
 
 #[tokio::test]
 async fn test_error_unauthorized() {
-    let mut server = Server::new();
+    let mut server = Server::new_async().await;
     let url = server.url();
     
     let _m = server
         .mock("GET", "/api/albums")
         .with_status(401)
-        .create();
+        .create_async()
+        .await;
     
     let client = create_test_client(&url);
     let result = client.get_albums().await;
     
-    assert!(matches!(result, Err(zerogallery::Error::Unauthorized)));
+    assert!(matches!(result, Err(zerogallery::Error::Unauthorized(_))));
 }
 
 #[tokio::test]
 async fn test_error_not_found() {
-    let mut server = Server::new();
+    let mut server = Server::new_async().await;
     let url = server.url();
     
     let _m = server
         .mock("GET", "/api/data/999")
         .with_status(404)
-        .create();
+        .create_async()
+        .await;
     
     let client = create_test_client(&url);
-    let result = client.get_data(999).await;
+    let result = client.get_data(DataId(999)).await;
     
     assert!(matches!(result, Err(zerogallery::Error::NotFound(_))));
 }
 
+#[tokio::test]
+async fn test_error_keeps_server_context() {
+    let mut server = Server::new_async().await;
+    let url = server.url();
+
+    let _denied = server
+        .mock("DELETE", "/api/album/4")
+        .with_status(401)
+        .create_async()
+        .await;
+    let _empty_name = server
+        .mock("POST", "/api/album")
+        .with_status(400)
+        .with_body("Empty album name")
+        .create_async()
+        .await;
+    let _range = server
+        .mock("GET", "/api/data/8")
+        .with_status(400)
+        .with_body("\"Invalid range\"")
+        .create_async()
+        .await;
+    let _broken = server
+        .mock("GET", "/api/albums")
+        .with_status(200)
+        .with_body("[{\"id\": \"not a number\"}]")
+        .create_async()
+        .await;
+
+    let client = create_test_client(&url);
+
+    let error = client.delete_album(AlbumId(4)).await.unwrap_err();
+    assert!(error.is_auth());
+    assert!(!error.is_retryable());
+    let context = error.context().unwrap();
+    assert_eq!(context.method, reqwest::Method::DELETE);
+    assert_eq!(context.url, format!("{}/api/album/4", url));
+    assert_eq!(context.operation, "delete_album");
+
+    let error = client
+        .create_album(CreateAlbumInfo {
+            name: String::new(),
+            description: String::new(),
+            token: String::new(),
+            allow_remove_data: false,
+        })
+        .await
+        .unwrap_err();
+    assert!(matches!(error, Error::EmptyAlbumName(_)));
+    assert_eq!(error.status(), Some(reqwest::StatusCode::BAD_REQUEST));
+
+    let error = client.get_video_stream(DataId(8), Some(100), Some(10)).await.unwrap_err();
+    assert!(matches!(error, Error::InvalidRange(_)));
+
+    match client.get_albums().await.unwrap_err() {
+        Error::InvalidResponse { context, source } => {
+            assert!(context.body.contains("not a number"));
+            assert!(source.is_data());
+        }
+        other => panic!("unexpected error: {:?}", other),
+    }
+}
+
 #[tokio::test]
 async fn test_multiple_file_upload() {
-    let mut server = Server::new();
+    let mut server = Server::new_async().await;
     let url = server.url();
     
     let _m = server
         .mock("POST", "/api/upload/1")
         .with_status(200)
         .with_body("[101, 102, 103]")
-        .create();
+        .create_async()
+        .await;
     
     let client = create_test_client(&url);
     
@@ -320,21 +413,2276 @@ async fn test_multiple_file_upload() {
         .collect();
     
     let file_refs: Vec<_> = files.iter().map(|p| p.as_path()).collect();
-    let ids = client.upload_multiple_files(&file_refs, 1).await.unwrap();
+    let ids = client.upload_multiple_files(&file_refs, AlbumId(1)).await.unwrap();
     
-    assert_eq!(ids, vec![101, 102, 103]);
+    assert_eq!(ids, vec![DataId(101), DataId(102), DataId(103)]);
+}
+
+#[tokio::test]
+async fn test_read_sends_access_token_only() {
+    let mut server = Server::new_async().await;
+    let url = server.url();
+
+    let _m = server
+        .mock("GET", "/api/data/1")
+        .match_header("X-ZERO-ACCESS-TOKEN", "test-token")
+        .match_header("X-ZERO-UPLOAD-TOKEN", Matcher::Missing)
+        .with_status(200)
+        .with_body("content")
+        .create_async()
+        .await;
+
+    let client = create_test_client(&url);
+    let data = client.get_data(DataId(1)).await.unwrap();
+    assert_eq!(data, b"content");
+}
+
+#[tokio::test]
+async fn test_create_album_sends_upload_token_only() {
+    let mut server = Server::new_async().await;
+    let url = server.url();
+
+    let _m = server
+        .mock("POST", "/api/album")
+        .match_header("X-ZERO-UPLOAD-TOKEN", "upload-token")
+        .match_header("X-ZERO-ACCESS-TOKEN", Matcher::Missing)
+        .with_status(200)
+        .with_body(r#"{"id":1,"imagePreviewId":0,"name":"A","description":"","isProtected":false}"#)
+        .create_async()
+        .await;
+
+    let client = create_test_client(&url);
+    client
+        .create_album(CreateAlbumInfo {
+            name: "A".to_string(),
+            description: String::new(),
+            token: String::new(),
+            allow_remove_data: true,
+        })
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+async fn test_delete_sends_upload_and_access_tokens() {
+    let mut server = Server::new_async().await;
+    let url = server.url();
+
+    let _m = server
+        .mock("DELETE", "/api/album/1")
+        .match_header("X-ZERO-UPLOAD-TOKEN", "upload-token")
+        .match_header("X-ZERO-ACCESS-TOKEN", "test-token")
+        .with_status(200)
+        .create_async()
+        .await;
+
+    let client = create_test_client(&url);
+    client.delete_album(AlbumId(1)).await.unwrap();
+}
+
+#[tokio::test]
+async fn test_master_token_sent_as_upload_token() {
+    let mut server = Server::new_async().await;
+    let url = server.url();
+
+    let read = server
+        .mock("GET", "/api/album/1/data")
+        .match_header("X-ZERO-UPLOAD-TOKEN", "master-token")
+        .with_status(200)
+        .with_body("[]")
+        .create_async()
+        .await;
+    let write = server
+        .mock("DELETE", "/api/data/1")
+        .match_header("X-ZERO-UPLOAD-TOKEN", "master-token")
+        .with_status(200)
+        .create_async()
+        .await;
+
+    let client = ZeroGalleryClient::with_credentials(
+        &url,
+        Credentials::new()
+            .with_upload_token("upload-token")
+            .with_master_token("master-token"),
+    );
+    client.get_album_data(AlbumId(1)).await.unwrap();
+    client.delete_data(DataId(1)).await.unwrap();
+
+    read.assert_async().await;
+    write.assert_async().await;
+}
+
+#[tokio::test]
+async fn test_album_token_used_for_album_and_its_data() {
+    let mut server = Server::new_async().await;
+    let url = server.url();
+
+    let list = server
+        .mock("GET", "/api/album/5/data")
+        .match_header("X-ZERO-ACCESS-TOKEN", "album-5-token")
+        .with_status(200)
+        .with_body(r#"[{"id":50,"albumId":5,"size":4,"createdTimestamp":0,"name":"a.jpg","extension":"jpg","description":"","mimeType":"image/jpeg","tags":""}]"#)
+        .create_async()
+        .await;
+    let data = server
+        .mock("GET", "/api/data/50")
+        .match_header("X-ZERO-ACCESS-TOKEN", "album-5-token")
+        .with_status(200)
+        .with_body("data")
+        .create_async()
+        .await;
+    let preview = server
+        .mock("GET", "/api/preview/50")
+        .match_header("X-ZERO-ACCESS-TOKEN", "album-5-token")
+        .with_status(200)
+        .with_body("jpeg")
+        .create_async()
+        .await;
+    let delete = server
+        .mock("DELETE", "/api/data/50")
+        .match_header("X-ZERO-UPLOAD-TOKEN", "upload-token")
+        .match_header("X-ZERO-ACCESS-TOKEN", "album-5-token")
+        .with_status(200)
+        .create_async()
+        .await;
+
+    let client = create_test_client(&url);
+    client.album_credentials().insert(AlbumId(5), "album-5-token");
+
+    client.get_album_data(AlbumId(5)).await.unwrap();
+    assert_eq!(client.get_data(DataId(50)).await.unwrap(), b"data");
+    assert_eq!(client.get_preview(DataId(50)).await.unwrap(), b"jpeg");
+    client.delete_data(DataId(50)).await.unwrap();
+
+    list.assert_async().await;
+    data.assert_async().await;
+    preview.assert_async().await;
+    delete.assert_async().await;
+    assert_eq!(client.album_credentials().album_of(DataId(50)), None);
+}
+
+#[tokio::test]
+async fn test_unknown_album_falls_back_to_global_token() {
+    let mut server = Server::new_async().await;
+    let url = server.url();
+
+    let _m = server
+        .mock("GET", "/api/data/77")
+        .match_header("X-ZERO-ACCESS-TOKEN", "test-token")
+        .with_status(200)
+        .with_body("data")
+        .create_async()
+        .await;
+
+    let client = create_test_client(&url);
+    client.album_credentials().insert(AlbumId(5), "album-5-token");
+    client.get_data(DataId(77)).await.unwrap();
+}
+
+#[tokio::test]
+async fn test_create_album_and_upload_register_tokens() {
+    let mut server = Server::new_async().await;
+    let url = server.url();
+
+    let _album = server
+        .mock("POST", "/api/album")
+        .with_status(200)
+        .with_body(r#"{"id":9,"imagePreviewId":0,"name":"Secret","description":"","isProtected":true}"#)
+        .create_async()
+        .await;
+    let upload = server
+        .mock("POST", "/api/upload/9")
+        .match_header("X-ZERO-ACCESS-TOKEN", "secret-token")
+        .with_status(200)
+        .with_body("90")
+        .create_async()
+        .await;
+    let download = server
+        .mock("GET", "/api/data/90")
+        .match_header("X-ZERO-ACCESS-TOKEN", "secret-token")
+        .with_status(200)
+        .with_body("data")
+        .create_async()
+        .await;
+
+    let registry = AlbumCredentials::new();
+    let mut client = create_test_client(&url);
+    client.set_album_credentials(registry.clone());
+
+    let album = client
+        .create_album(CreateAlbumInfo {
+            name: "Secret".to_string(),
+            description: String::new(),
+            token: "secret-token".to_string(),
+            allow_remove_data: false,
+        })
+        .await
+        .unwrap();
+    let id = client
+        .upload_file_data(b"data", "a.bin", album.id)
+        .await
+        .unwrap();
+    client.get_data(id).await.unwrap();
+
+    upload.assert_async().await;
+    download.assert_async().await;
+    assert_eq!(registry.token(AlbumId(9)).as_deref(), Some("secret-token"));
+}
+
+#[tokio::test]
+async fn test_builder_user_agent_and_default_headers() {
+    let mut server = Server::new_async().await;
+    let url = server.url();
+
+    let _m = server
+        .mock("GET", "/api/version")
+        .match_header("user-agent", "gallery-sync/2.0")
+        .match_header("x-request-source", "nightly")
+        .with_status(200)
+        .with_body("1.2")
+        .create_async()
+        .await;
+
+    let client = ZeroGalleryClient::builder(&url)
+        .user_agent("gallery-sync/2.0")
+        .default_header(
+            reqwest::header::HeaderName::from_static("x-request-source"),
+            reqwest::header::HeaderValue::from_static("nightly"),
+        )
+        .connect_timeout(Duration::from_secs(5))
+        .pool_max_idle_per_host(4)
+        .build()
+        .unwrap();
+    assert_eq!(client.get_version().await.unwrap(), "1.2");
+}
+
+#[tokio::test]
+async fn test_builder_with_custom_http_client() {
+    let mut server = Server::new_async().await;
+    let url = server.url();
+
+    let _m = server
+        .mock("GET", "/api/version")
+        .with_status(200)
+        .with_body("1.2")
+        .create_async()
+        .await;
+
+    let client = ZeroGalleryClient::builder(&url)
+        .http_client(reqwest::Client::new())
+        .read_timeout(Duration::from_secs(5))
+        .build()
+        .unwrap();
+    assert_eq!(client.get_version().await.unwrap(), "1.2");
+
+    let conflict = ZeroGalleryClient::builder(&url)
+        .http_client(reqwest::Client::new())
+        .user_agent("ignored")
+        .build();
+    assert!(matches!(conflict, Err(Error::Config(_))));
+}
+
+#[test]
+fn test_builder_rejects_invalid_configuration() {
+    assert!(matches!(
+        ZeroGalleryClient::builder("").build(),
+        Err(Error::Config(_))
+    ));
+    assert!(ZeroGalleryClient::builder("http://localhost")
+        .proxy("not a url")
+        .build()
+        .is_err());
+}
+
+#[tokio::test]
+async fn test_read_timeout() {
+    // Сервер принимает соединение, но не отвечает
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        let (_socket, _) = listener.accept().await.unwrap();
+        tokio::time::sleep(Duration::from_secs(10)).await;
+    });
+
+    let client = ZeroGalleryClient::builder(format!("http://{}", addr))
+        .read_timeout(Duration::from_millis(200))
+        .retry_policy(RetryPolicy::disabled())
+        .build()
+        .unwrap();
+    let result = client.get_albums().await;
+    assert!(matches!(result, Err(Error::ReadTimeout(_))));
+}
+
+#[tokio::test]
+async fn test_body_error_is_retryable() {
+    // Сервер обещает 100 байт, отдает 10 и закрывает соединение
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+        let (mut socket, _) = listener.accept().await.unwrap();
+        let mut request = [0u8; 1024];
+        let _ = socket.read(&mut request).await.unwrap();
+        socket
+            .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 100\r\n\r\n0123456789")
+            .await
+            .unwrap();
+    });
+
+    let client = ZeroGalleryClient::builder(format!("http://{}", addr))
+        .retry_policy(RetryPolicy::disabled())
+        .build()
+        .unwrap();
+    let error = client.get_data(DataId(1)).await.unwrap_err();
+    assert!(matches!(&error, Error::Request(e) if e.is_body()), "{:?}", error);
+    assert!(error.is_retryable());
+}
+
+#[tokio::test]
+async fn test_client_from_profile() {
+    let mut server = Server::new_async().await;
+    let url = server.url();
+
+    let _m = server
+        .mock("GET", "/api/album/3/data")
+        .match_header("X-ZERO-ACCESS-TOKEN", "album-3-token")
+        .with_status(200)
+        .with_body("[]")
+        .create_async()
+        .await;
+
+    let config_dir = tempfile::tempdir().unwrap();
+    let path = config_dir.path().join("config.toml");
+    std::fs::write(
+        &path,
+        "[profiles.test]\nurl = \"http://unused.test\"\naccess_token = \"global\"\n\n[profiles.test.albums]\n3 = \"album-3-token\"\n",
+    )
+    .unwrap();
+
+    // Адрес сервера переопределяется переменной, окружение процесса не читается
+    let lookup = |key: &str| (key == zerogallery::ENV_URL).then(|| url.clone());
+    let profile = Profile::load_from_with(&path, "test", lookup).unwrap();
+    let client = ZeroGalleryClientBuilder::from_profile(&profile).unwrap().build().unwrap();
+    client.get_album_data(AlbumId(3)).await.unwrap();
+
+    assert!(matches!(
+        Profile::load_from_with(&path, "missing", |_| None),
+        Err(Error::Profile(_))
+    ));
+}
+
+/// Транспорт в памяти: запоминает запросы и отвечает фрагментами `chunks`
+#[derive(Default)]
+struct RecordingTransport {
+    requests: Mutex<Vec<(String, String, String)>>,
+    chunks: Vec<&'static str>,
+}
+
+impl Transport for RecordingTransport {
+    fn send(&self, request: ApiRequest) -> futures_util::future::BoxFuture<'static, zerogallery::Result<ApiResponse>> {
+        let access = request
+            .headers
+            .get("X-ZERO-ACCESS-TOKEN")
+            .map(|v| v.to_str().unwrap().to_string())
+            .unwrap_or_default();
+        self.requests
+            .lock()
+            .unwrap()
+            .push((request.operation.to_string(), format!("{} {}", request.method, request.url), access));
+        let chunks: Vec<zerogallery::Result<bytes::Bytes>> = self
+            .chunks
+            .iter()
+            .map(|chunk| Ok(bytes::Bytes::from_static(chunk.as_bytes())))
+            .collect();
+        Box::pin(async move {
+            Ok(ApiResponse {
+                status: reqwest::StatusCode::OK,
+                headers: reqwest::header::HeaderMap::new(),
+                body: Box::pin(futures_util::stream::iter(chunks)),
+            })
+        })
+    }
+}
+
+#[tokio::test]
+async fn test_custom_transport_receives_all_calls() {
+    let transport = Arc::new(RecordingTransport {
+        chunks: vec!["[", "]"],
+        ..Default::default()
+    });
+    let client = ZeroGalleryClient::builder("http://gallery.test/")
+        .credentials(Credentials::new().with_access_token("test-token"))
+        .transport(transport.clone())
+        .build()
+        .unwrap();
+
+    assert!(client.get_albums().await.unwrap().is_empty());
+    assert_eq!(client.get_data(DataId(5)).await.unwrap(), b"[]");
+    client.delete_data(DataId(5)).await.unwrap();
+
+    let requests = transport.requests.lock().unwrap();
+    assert_eq!(
+        *requests,
+        vec![
+            ("get_albums".to_string(), "GET http://gallery.test/api/albums".to_string(), "test-token".to_string()),
+            ("get_data".to_string(), "GET http://gallery.test/api/data/5".to_string(), "test-token".to_string()),
+            ("delete_data".to_string(), "DELETE http://gallery.test/api/data/5".to_string(), "test-token".to_string()),
+        ]
+    );
+}
+
+/// Транспорт, имитирующий сетевой сбой
+struct FailingTransport;
+
+impl Transport for FailingTransport {
+    fn send(&self, _request: ApiRequest) -> futures_util::future::BoxFuture<'static, zerogallery::Result<ApiResponse>> {
+        Box::pin(async {
+            Err(Error::Transport(Box::new(std::io::Error::new(
+                std::io::ErrorKind::ConnectionReset,
+                "simulated reset",
+            ))))
+        })
+    }
+}
+
+#[tokio::test]
+async fn test_custom_transport_errors_propagate() {
+    let client = ZeroGalleryClient::builder("http://gallery.test")
+        .transport(FailingTransport)
+        .build()
+        .unwrap();
+    assert!(matches!(client.get_version().await, Err(Error::Transport(_))));
+
+    let conflict = ZeroGalleryClient::builder("http://gallery.test")
+        .transport(FailingTransport)
+        .http_client(reqwest::Client::new())
+        .build();
+    assert!(matches!(conflict, Err(Error::Config(_))));
+}
+
+#[tokio::test]
+async fn test_upload_sends_multipart_form() {
+    let mut server = Server::new_async().await;
+    let url = server.url();
+
+    let _m = server
+        .mock("POST", "/api/upload")
+        .match_header("content-type", Matcher::Regex("^multipart/form-data; boundary=".to_string()))
+        .match_header("content-length", Matcher::Any)
+        .match_body(Matcher::Regex(
+            r#"name="file"; filename="notes.txt"\r\nContent-Type: application/octet-stream\r\n\r\nhello\r\n"#.to_string(),
+        ))
+        .with_status(200)
+        .with_body("11")
+        .create_async()
+        .await;
+
+    let client = create_test_client(&url);
+    assert_eq!(client.upload_file_data(b"hello", "notes.txt", UploadTarget::Public).await.unwrap(), DataId(11));
+}
+
+/// Политика с короткими паузами для тестов
+fn fast_retries() -> RetryPolicy {
+    RetryPolicy::new().with_backoff(Duration::from_millis(1), Duration::from_millis(5))
+}
+
+#[tokio::test]
+async fn test_idempotent_calls_retry_transient_errors() {
+    let mut server = Server::new_async().await;
+    let url = server.url();
+
+    let bad_gateway = server
+        .mock("GET", "/api/albums")
+        .with_status(502)
+        .expect(2)
+        .create_async()
+        .await;
+    let ok = server
+        .mock("GET", "/api/albums")
+        .with_status(200)
+        .with_body("[]")
+        .expect(1)
+        .create_async()
+        .await;
+    let delete = server
+        .mock("DELETE", "/api/data/4")
+        .with_status(503)
+        .with_header("retry-after", "0")
+        .expect(3)
+        .create_async()
+        .await;
+
+    let client = ZeroGalleryClient::builder(&url)
+        .retry_policy(fast_retries().with_max_retries(2))
+        .build()
+        .unwrap();
+
+    assert!(client.get_albums().await.unwrap().is_empty());
+    bad_gateway.assert_async().await;
+    ok.assert_async().await;
+
+    // Повторы исчерпаны: последний ответ возвращается как ошибка API
+    let error = client.delete_data(DataId(4)).await.unwrap_err();
+    assert!(matches!(error, Error::Api(_)));
+    assert!(error.is_retryable());
+    assert_eq!(error.status(), Some(reqwest::StatusCode::SERVICE_UNAVAILABLE));
+    delete.assert_async().await;
+}
+
+#[tokio::test]
+async fn test_non_idempotent_calls_not_retried_by_default() {
+    let mut server = Server::new_async().await;
+    let url = server.url();
+
+    let upload = server
+        .mock("POST", "/api/upload")
+        .with_status(502)
+        .expect(1)
+        .create_async()
+        .await;
+
+    let client = ZeroGalleryClient::builder(&url)
+        .retry_policy(fast_retries())
+        .build()
+        .unwrap();
+    let error = client.upload_file_data(b"data", "a.txt", UploadTarget::Public).await.unwrap_err();
+    assert_eq!(error.status(), Some(reqwest::StatusCode::BAD_GATEWAY));
+    upload.assert_async().await;
+}
+
+#[tokio::test]
+async fn test_create_album_reconciles_before_retry() {
+    let mut server = Server::new_async().await;
+    let url = server.url();
+
+    // Первый список пуст, после сбоя альбом уже создан
+    let listings = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+    let counter = listings.clone();
+    let _albums = server
+        .mock("GET", "/api/albums")
+        .with_status(200)
+        .with_body_from_request(move |_| {
+            if counter.fetch_add(1, Ordering::SeqCst) == 0 {
+                b"[]".to_vec()
+            } else {
+                br#"[{"id": 9, "imagePreviewId": 0, "name": "Trip", "description": "", "isProtected": true}]"#.to_vec()
+            }
+        })
+        .create_async()
+        .await;
+    let create = server
+        .mock("POST", "/api/album")
+        .with_status(504)
+        .expect(1)
+        .create_async()
+        .await;
+
+    let client = ZeroGalleryClient::builder(&url)
+        .retry_policy(fast_retries().with_safe_reconciliation(true))
+        .build()
+        .unwrap();
+    let album = client
+        .create_album(CreateAlbumInfo {
+            name: "Trip".to_string(),
+            description: String::new(),
+            token: "trip-token".to_string(),
+            allow_remove_data: false,
+        })
+        .await
+        .unwrap();
+
+    assert_eq!(album.id, AlbumId(9));
+    assert_eq!(listings.load(Ordering::SeqCst), 2);
+    assert_eq!(client.album_credentials().token(AlbumId(9)).as_deref(), Some("trip-token"));
+    create.assert_async().await;
+}
+
+#[tokio::test]
+async fn test_upload_retried_when_reconciliation_finds_nothing() {
+    let mut server = Server::new_async().await;
+    let url = server.url();
+
+    let _listing = server
+        .mock("GET", "/api/album/3/data")
+        .with_status(200)
+        .with_body("[]")
+        .expect(2)
+        .create_async()
+        .await;
+    let unavailable = server
+        .mock("POST", "/api/upload/3")
+        .with_status(503)
+        .expect(1)
+        .create_async()
+        .await;
+    let uploaded = server
+        .mock("POST", "/api/upload/3")
+        .with_status(200)
+        .with_body("42")
+        .expect(1)
+        .create_async()
+        .await;
+
+    let client = ZeroGalleryClient::builder(&url)
+        .retry_policy(fast_retries().with_safe_reconciliation(true))
+        .build()
+        .unwrap();
+    assert_eq!(client.upload_file_data(b"data", "a.txt", AlbumId(3)).await.unwrap(), DataId(42));
+    unavailable.assert_async().await;
+    uploaded.assert_async().await;
+}
+
+#[tokio::test]
+async fn test_reads_fail_over_to_replica() {
+    let mut primary = Server::new_async().await;
+    let mut replica = Server::new_async().await;
+
+    let primary_albums = primary
+        .mock("GET", "/api/albums")
+        .with_status(503)
+        .expect(1)
+        .create_async()
+        .await;
+    let replica_albums = replica
+        .mock("GET", "/api/albums")
+        .with_status(200)
+        .with_body("[]")
+        .expect(2)
+        .create_async()
+        .await;
+    let replica_delete = replica
+        .mock("DELETE", "/api/data/1")
+        .expect(0)
+        .create_async()
+        .await;
+
+    let client = ZeroGalleryClient::builder(primary.url())
+        .endpoint(Endpoint::read_only(replica.url()))
+        .circuit_breaker(CircuitBreakerPolicy::new().with_failure_threshold(1))
+        .retry_policy(fast_retries())
+        .build()
+        .unwrap();
+
+    assert!(client.get_albums().await.unwrap().is_empty());
+    assert!(client.get_albums().await.unwrap().is_empty());
+    let health = client.endpoint_health();
+    assert_eq!(health[0].state, CircuitState::Open);
+    assert_eq!(health[1].state, CircuitState::Closed);
+    assert!(!health[1].endpoint.is_writable());
+
+    // Запись не уходит на реплику, даже если основной сервер недоступен
+    assert!(matches!(client.delete_data(DataId(1)).await, Err(Error::Unavailable(_))));
+
+    primary_albums.assert_async().await;
+    replica_albums.assert_async().await;
+    replica_delete.assert_async().await;
+}
+
+#[tokio::test]
+async fn test_open_endpoint_probed_with_get_version() {
+    let mut primary = Server::new_async().await;
+    let replica = Server::new_async().await;
+
+    let failure = primary
+        .mock("GET", "/api/albums")
+        .with_status(502)
+        .expect(1)
+        .create_async()
+        .await;
+
+    let client = ZeroGalleryClient::builder(primary.url())
+        .endpoint(Endpoint::read_only(replica.url()))
+        .circuit_breaker(
+            CircuitBreakerPolicy::new()
+                .with_failure_threshold(1)
+                .with_open_duration(Duration::from_millis(50)),
+        )
+        .retry_policy(RetryPolicy::disabled())
+        .build()
+        .unwrap();
+
+    let error = client.get_albums().await.unwrap_err();
+    assert_eq!(error.status(), Some(reqwest::StatusCode::BAD_GATEWAY));
+    assert_eq!(client.endpoint_health()[0].state, CircuitState::Open);
+    failure.assert_async().await;
+
+    let probe = primary
+        .mock("GET", "/api/version")
+        .with_status(200)
+        .with_body("1.0")
+        .expect(1)
+        .create_async()
+        .await;
+    let recovered = primary
+        .mock("GET", "/api/albums")
+        .with_status(200)
+        .with_body("[]")
+        .expect(1)
+        .create_async()
+        .await;
+    tokio::time::sleep(Duration::from_millis(60)).await;
+
+    assert!(client.get_albums().await.unwrap().is_empty());
+    assert_eq!(client.endpoint_health()[0].state, CircuitState::Closed);
+    probe.assert_async().await;
+    recovered.assert_async().await;
+}
+
+#[tokio::test]
+async fn test_rate_limiter_requests_per_second() {
+    let mut server = Server::new_async().await;
+    let url = server.url();
+
+    let _m = server
+        .mock("GET", "/api/version")
+        .with_status(200)
+        .with_body("1.0")
+        .expect(8)
+        .create_async()
+        .await;
+
+    // Запас 5 запросов, остальные три с интервалом 200 мс
+    let client = ZeroGalleryClient::builder(&url)
+        .rate_limiter(RateLimiter::new().with_shared(RateLimit::new().with_requests_per_second(5.0)))
+        .build()
+        .unwrap();
+    let started = std::time::Instant::now();
+    for _ in 0..8 {
+        client.get_version().await.unwrap();
+    }
+    assert!(started.elapsed() >= Duration::from_millis(550), "{:?}", started.elapsed());
+}
+
+#[tokio::test]
+async fn test_rate_limiter_bytes_per_second() {
+    let mut server = Server::new_async().await;
+    let url = server.url();
+
+    let _m = server
+        .mock("GET", "/api/data/1")
+        .with_status(200)
+        .with_body(vec![7u8; 30_000])
+        .create_async()
+        .await;
+
+    let client = ZeroGalleryClient::builder(&url)
+        .rate_limiter(
+            RateLimiter::new().with_class(TrafficClass::Download, RateLimit::new().with_bytes_per_second(20_000)),
+        )
+        .build()
+        .unwrap();
+    let started = std::time::Instant::now();
+    assert_eq!(client.get_data(DataId(1)).await.unwrap().len(), 30_000);
+    assert!(started.elapsed() >= Duration::from_millis(450), "{:?}", started.elapsed());
+}
+
+#[tokio::test]
+async fn test_rate_limiter_split_budgets() {
+    let mut server = Server::new_async().await;
+    let url = server.url();
+
+    let _albums = server
+        .mock("GET", "/api/albums")
+        .with_status(200)
+        .with_body("[]")
+        .expect(5)
+        .create_async()
+        .await;
+
+    // Бюджет загрузок исчерпан, списки не ждут
+    let limiter = RateLimiter::new().with_class(TrafficClass::Upload, RateLimit::new().with_requests_per_second(0.1));
+    limiter.acquire_request(TrafficClass::Upload).await;
+    let client = ZeroGalleryClient::builder(&url)
+        .rate_limiter(limiter.clone())
+        .build()
+        .unwrap();
+    let started = std::time::Instant::now();
+    for _ in 0..5 {
+        client.get_albums().await.unwrap();
+    }
+    assert!(started.elapsed() < Duration::from_secs(1));
+}
+
+#[tokio::test]
+async fn test_client_metrics() {
+    let mut server = Server::new_async().await;
+    let url = server.url();
+
+    let _albums = server
+        .mock("GET", "/api/albums")
+        .with_status(200)
+        .with_body("[]")
+        .create_async()
+        .await;
+    let _missing = server
+        .mock("GET", "/api/data/5")
+        .with_status(404)
+        .create_async()
+        .await;
+    let _data = server
+        .mock("GET", "/api/data/6")
+        .with_status(200)
+        .with_body("0123456789")
+        .create_async()
+        .await;
+    let _upload = server
+        .mock("POST", "/api/upload")
+        .with_status(200)
+        .with_body("7")
+        .create_async()
+        .await;
+
+    let metrics = ClientMetrics::new();
+    let client = ZeroGalleryClient::builder(&url).metrics(metrics.clone()).build().unwrap();
+    client.get_albums().await.unwrap();
+    client.get_albums().await.unwrap();
+    assert!(matches!(client.get_data(DataId(5)).await, Err(Error::NotFound(_))));
+    client.get_data(DataId(6)).await.unwrap();
+    client.upload_file_data(b"hello", "a.txt", UploadTarget::Public).await.unwrap();
+
+    let snapshot = client.metrics().snapshot();
+    let albums = &snapshot.operations["get_albums"];
+    assert_eq!(albums.requests, 2);
+    assert_eq!(albums.latency.count, 2);
+    let data = &snapshot.operations["get_data"];
+    assert_eq!(data.statuses.get(&404), Some(&1));
+    assert_eq!(data.errors.get(&ErrorClass::NotFound), Some(&1));
+    assert_eq!(data.bytes_downloaded, 10);
+    assert!(snapshot.operations["upload_file_data"].bytes_uploaded > 5);
+
+    let text = metrics.to_prometheus();
+    assert!(text.contains("# TYPE zerogallery_requests_total counter"));
+    assert!(text.contains("zerogallery_requests_total{operation=\"get_albums\",status=\"200\"} 2"));
+    assert!(text.contains("zerogallery_errors_total{operation=\"get_data\",class=\"not_found\"} 1"));
+    assert!(text.contains("zerogallery_downloaded_bytes_total{operation=\"get_data\"} 10"));
+    assert!(text.contains("zerogallery_request_duration_seconds_count{operation=\"get_albums\"} 2"));
+}
+
+#[test]
+fn test_retry_backoff_is_bounded() {
+    let policy = RetryPolicy::new().with_backoff(Duration::from_millis(100), Duration::from_secs(1));
+    for retry in 1..10 {
+        let delay = policy.backoff(retry);
+        let cap = Duration::from_millis(100 * 2u64.pow(retry - 1)).min(Duration::from_secs(1));
+        assert!(delay >= cap / 2 && delay <= cap, "retry {}: {:?}", retry, delay);
+    }
+}
+
+#[test]
+fn test_credentials_debug_hides_tokens() {
+    let credentials = Credentials::new().with_access_token("secret");
+    let debug = format!("{:?}", credentials);
+    assert!(!debug.contains("secret"));
+}
+
+/// Транспорт, отдающий первую порцию тела и затем зависающий
+struct StalledTransport;
+
+impl Transport for StalledTransport {
+    fn send(&self, _request: ApiRequest) -> futures_util::future::BoxFuture<'static, zerogallery::Result<ApiResponse>> {
+        let first = futures_util::stream::iter([Ok(bytes::Bytes::from_static(&[1u8; 1024]))]);
+        Box::pin(async move {
+            Ok(ApiResponse {
+                status: reqwest::StatusCode::OK,
+                headers: reqwest::header::HeaderMap::new(),
+                body: Box::pin(futures_util::StreamExt::chain(first, futures_util::stream::pending())),
+            })
+        })
+    }
+}
+
+#[tokio::test]
+async fn test_download_cancellation() {
+    let client = ZeroGalleryClient::builder("http://gallery.test")
+        .transport(StalledTransport)
+        .build()
+        .unwrap();
+    let temp_dir = tempfile::tempdir().unwrap();
+
+    for (partial_file, kept) in [(PartialFile::Remove, false), (PartialFile::Keep, true)] {
+        let path = temp_dir.path().join(format!("{:?}.bin", partial_file));
+        let token = CancellationToken::new();
+        let canceller = token.clone();
+        let options = TransferOptions::new()
+            .with_cancellation(token)
+            .with_partial_file(partial_file)
+            .with_progress(Box::new(move |_, _| canceller.cancel()));
+
+        let result = client.download_data_with(DataId(3), &path, options).await;
+        assert!(matches!(result, Err(Error::Cancelled)), "{:?}", result);
+        assert_eq!(path.exists(), kept);
+    }
+}
+
+/// Транспорт, обрывающий полный ответ на середине и отвечающий 206 на запрос с `Range`
+struct InterruptedTransport {
+    content: Vec<u8>,
+    ranges: Arc<Mutex<Vec<String>>>,
+}
+
+impl Transport for InterruptedTransport {
+    fn send(&self, request: ApiRequest) -> futures_util::future::BoxFuture<'static, zerogallery::Result<ApiResponse>> {
+        let content = bytes::Bytes::from(self.content.clone());
+        let range = request.headers.get("range").map(|value| value.to_str().unwrap().to_string());
+        self.ranges.lock().unwrap().extend(range.clone());
+        Box::pin(async move {
+            let mut headers = reqwest::header::HeaderMap::new();
+            let (status, body): (_, zerogallery::ResponseBody) = match range {
+                Some(range) => {
+                    let start: usize = range["bytes=".len()..range.len() - 1].parse().unwrap();
+                    let content_range = format!("bytes {}-{}/{}", start, content.len() - 1, content.len());
+                    headers.insert("content-range", content_range.parse().unwrap());
+                    let rest = content.slice(start..);
+                    (reqwest::StatusCode::PARTIAL_CONTENT, Box::pin(futures_util::stream::iter([Ok(rest)])))
+                }
+                None => {
+                    let reset = std::io::Error::from(std::io::ErrorKind::ConnectionReset);
+                    let chunks = [Ok(content.slice(..content.len() / 2)), Err(Error::Io(reset))];
+                    (reqwest::StatusCode::OK, Box::pin(futures_util::stream::iter(chunks)))
+                }
+            };
+            Ok(ApiResponse { status, headers, body })
+        })
+    }
+}
+
+fn video_info(id: i64, size: usize) -> DataInfo {
+    DataInfo {
+        id: DataId(id),
+        size: size as i64,
+        name: "clip.mp4".to_string(),
+        extension: ".mp4".to_string(),
+        mime_type: "video/mp4".to_string(),
+        ..DataInfo::default()
+    }
+}
+
+#[tokio::test]
+async fn test_download_resumes_after_interruption() {
+    let content: Vec<u8> = (0..4000u32).map(|i| (i % 251) as u8).collect();
+    let ranges = Arc::new(Mutex::new(Vec::new()));
+    let client = ZeroGalleryClient::builder("http://gallery.test")
+        .transport(InterruptedTransport {
+            content: content.clone(),
+            ranges: ranges.clone(),
+        })
+        .build()
+        .unwrap();
+    let temp_dir = tempfile::tempdir().unwrap();
+    let path = temp_dir.path().join("clip.mp4");
+
+    client
+        .download_data_resumable(&video_info(8, content.len()), &path, TransferOptions::new())
+        .await
+        .unwrap();
+
+    assert_eq!(std::fs::read(&path).unwrap(), content);
+    assert_eq!(*ranges.lock().unwrap(), ["bytes=2000-"]);
+    assert!(!temp_dir.path().join("clip.mp4.part").exists());
+    assert!(!temp_dir.path().join("clip.mp4.part.meta").exists());
+}
+
+#[tokio::test]
+async fn test_download_resumes_after_dropped_connection() {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    // Первый ответ обещает весь файл и закрывает соединение на середине,
+    // запрос диапазона получает остаток
+    let content: Vec<u8> = (0..4000u32).map(|i| (i % 251) as u8).collect();
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let served = content.clone();
+    let ranges = Arc::new(Mutex::new(Vec::new()));
+    let seen = ranges.clone();
+    tokio::spawn(async move {
+        loop {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut request = Vec::new();
+            while !request.ends_with(b"\r\n\r\n") {
+                let mut byte = [0u8; 1];
+                if socket.read(&mut byte).await.unwrap() == 0 {
+                    break;
+                }
+                request.push(byte[0]);
+            }
+            let request = String::from_utf8(request).unwrap().to_lowercase();
+            let range = request
+                .lines()
+                .find_map(|line| line.strip_prefix("range: bytes="))
+                .map(|range| range.trim_end_matches('-').parse::<usize>().unwrap());
+            let response = match range {
+                Some(start) => {
+                    seen.lock().unwrap().push(start);
+                    let mut response = format!(
+                        "HTTP/1.1 206 Partial Content\r\nContent-Length: {}\r\nContent-Range: bytes {}-{}/{}\r\n\r\n",
+                        served.len() - start,
+                        start,
+                        served.len() - 1,
+                        served.len()
+                    )
+                    .into_bytes();
+                    response.extend_from_slice(&served[start..]);
+                    response
+                }
+                None => {
+                    let mut response =
+                        format!("HTTP/1.1 200 OK\r\nContent-Length: {}\r\n\r\n", served.len()).into_bytes();
+                    response.extend_from_slice(&served[..2000]);
+                    response
+                }
+            };
+            socket.write_all(&response).await.unwrap();
+            socket.shutdown().await.unwrap();
+        }
+    });
+
+    let client = ZeroGalleryClient::new(format!("http://{}", addr));
+    let temp_dir = tempfile::tempdir().unwrap();
+    let path = temp_dir.path().join("clip.mp4");
+    client
+        .download_data_resumable(&video_info(8, content.len()), &path, TransferOptions::new())
+        .await
+        .unwrap();
+
+    assert_eq!(std::fs::read(&path).unwrap(), content);
+    assert_eq!(*ranges.lock().unwrap(), [2000]);
+    assert!(!temp_dir.path().join("clip.mp4.part").exists());
+}
+
+/// Отменить скачивание после первых 1024 байт, оставив `.part`
+async fn leave_partial_download(info: &DataInfo, path: &std::path::Path) {
+    let stalled = ZeroGalleryClient::builder("http://gallery.test")
+        .transport(StalledTransport)
+        .build()
+        .unwrap();
+    let token = CancellationToken::new();
+    let canceller = token.clone();
+    let options = TransferOptions::new()
+        .with_cancellation(token)
+        .with_progress(Box::new(move |_, _| canceller.cancel()));
+    let result = stalled.download_data_resumable(info, path, options).await;
+    assert!(matches!(result, Err(Error::Cancelled)), "{:?}", result);
+    let mut part = path.as_os_str().to_owned();
+    part.push(".part");
+    assert_eq!(std::fs::metadata(part).unwrap().len(), 1024);
+}
+
+#[tokio::test]
+async fn test_download_resume_restarts_when_range_ignored() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let path = temp_dir.path().join("photo.jpg");
+    let mut info = video_info(5, 1536);
+    info.extension = ".jpg".to_string();
+    info.mime_type = "image/jpeg".to_string();
+    leave_partial_download(&info, &path).await;
+
+    // Сервер игнорирует Range для изображений и отдает файл целиком
+    let mut server = Server::new_async().await;
+    let _m = server
+        .mock("GET", "/api/data/5")
+        .match_header("range", "bytes=1024-")
+        .with_status(200)
+        .with_body(vec![2u8; 1536])
+        .create_async()
+        .await;
+    let client = create_test_client(&server.url());
+    client
+        .download_data_resumable(&info, &path, TransferOptions::new())
+        .await
+        .unwrap();
+    assert_eq!(std::fs::read(&path).unwrap(), vec![2u8; 1536]);
+}
+
+#[tokio::test]
+async fn test_download_resume_restarts_on_wrong_range() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let path = temp_dir.path().join("clip.mp4");
+    let info = video_info(6, 1536);
+    leave_partial_download(&info, &path).await;
+
+    // Ответ 206 не с запрошенного смещения: `.part` очищается, скачивание идет с нуля
+    let mut server = Server::new_async().await;
+    let wrong = server
+        .mock("GET", "/api/data/6")
+        .match_header("range", "bytes=1024-")
+        .with_status(206)
+        .with_header("content-range", "bytes 512-1535/1536")
+        .with_body(vec![7u8; 1024])
+        .expect(1)
+        .create_async()
+        .await;
+    let full = server
+        .mock("GET", "/api/data/6")
+        .match_header("range", Matcher::Missing)
+        .with_status(200)
+        .with_body(vec![3u8; 1536])
+        .expect(1)
+        .create_async()
+        .await;
+    let client = create_test_client(&server.url());
+    client
+        .download_data_resumable(&info, &path, TransferOptions::new())
+        .await
+        .unwrap();
+    assert_eq!(std::fs::read(&path).unwrap(), vec![3u8; 1536]);
+    wrong.assert_async().await;
+    full.assert_async().await;
+}
+
+/// Транспорт, отдающий содержимое целиком или, если `ranges`, диапазонами с 206
+struct RangeTransport {
+    content: bytes::Bytes,
+    ranges: bool,
+    /// Диапазон с этого начала получает ответ 200 с файлом целиком
+    ignored: Option<usize>,
+    requests: Arc<Mutex<Vec<Option<String>>>>,
+}
+
+impl Transport for RangeTransport {
+    fn send(&self, request: ApiRequest) -> futures_util::future::BoxFuture<'static, zerogallery::Result<ApiResponse>> {
+        let range = request.headers.get("range").map(|value| value.to_str().unwrap().to_string());
+        self.requests.lock().unwrap().push(range.clone());
+        let content = self.content.clone();
+        let ranges = self.ranges;
+        let ignored = self.ignored;
+        Box::pin(async move {
+            let mut headers = reqwest::header::HeaderMap::new();
+            headers.insert("content-type", "video/mp4".parse().unwrap());
+            let (status, body) = match range.filter(|_| ranges) {
+                Some(range) => {
+                    let (start, end) = range["bytes=".len()..].split_once('-').unwrap();
+                    let start: usize = start.parse().unwrap();
+                    if ignored == Some(start) {
+                        return Ok(ApiResponse {
+                            status: reqwest::StatusCode::OK,
+                            headers,
+                            body: Box::pin(futures_util::stream::iter(vec![Ok(content)])),
+                        });
+                    }
+                    let end = end.parse().unwrap_or(content.len() - 1);
+                    let content_range = format!("bytes {}-{}/{}", start, end, content.len());
+                    headers.insert("content-range", content_range.parse().unwrap());
+                    (reqwest::StatusCode::PARTIAL_CONTENT, content.slice(start..=end))
+                }
+                None => (reqwest::StatusCode::OK, content),
+            };
+            let chunks: Vec<_> = body.chunks(100_000).map(|chunk| Ok(bytes::Bytes::copy_from_slice(chunk))).collect();
+            Ok(ApiResponse {
+                status,
+                headers,
+                body: Box::pin(futures_util::stream::iter(chunks)),
+            })
+        })
+    }
+}
+
+fn range_client(content: &[u8], ranges: bool) -> (ZeroGalleryClient, Arc<Mutex<Vec<Option<String>>>>) {
+    let requests = Arc::new(Mutex::new(Vec::new()));
+    let client = ZeroGalleryClient::builder("http://gallery.test")
+        .transport(RangeTransport {
+            content: bytes::Bytes::copy_from_slice(content),
+            ranges,
+            ignored: None,
+            requests: requests.clone(),
+        })
+        .build()
+        .unwrap();
+    (client, requests)
+}
+
+#[tokio::test]
+async fn test_download_data_parallel() {
+    let mib = zerogallery::PARALLEL_SEGMENT_MIN as usize;
+    let content: Vec<u8> = (0..3 * mib + 10).map(|i| (i % 253) as u8).collect();
+    let (client, requests) = range_client(&content, true);
+    let temp_dir = tempfile::tempdir().unwrap();
+    let path = temp_dir.path().join("clip.mp4");
+
+    let progress = Arc::new(Mutex::new(0));
+    let last = progress.clone();
+    let options = TransferOptions::new().with_progress(Box::new(move |done, _| *last.lock().unwrap() = done));
+    client
+        .download_data_parallel(&video_info(8, content.len()), &path, 8, options)
+        .await
+        .unwrap();
+
+    assert_eq!(std::fs::read(&path).unwrap(), content);
+    assert_eq!(*progress.lock().unwrap(), content.len() as u64);
+    let mut requests = requests.lock().unwrap().clone();
+    requests.sort();
+    let expected: Vec<_> = [(0, mib + 3), (mib + 4, 2 * mib + 7), (2 * mib + 8, 3 * mib + 9)]
+        .iter()
+        .map(|(start, end)| Some(format!("bytes={}-{}", start, end)))
+        .collect();
+    assert_eq!(requests, expected);
+}
+
+#[tokio::test]
+async fn test_download_data_parallel_falls_back_to_single_stream() {
+    let mib = zerogallery::PARALLEL_SEGMENT_MIN as usize;
+    let content: Vec<u8> = (0..2 * mib + 1).map(|i| (i % 241) as u8).collect();
+    let temp_dir = tempfile::tempdir().unwrap();
+
+    // Сервер игнорирует Range: первый ответ 200 дописывается целиком
+    let (client, requests) = range_client(&content, false);
+    let path = temp_dir.path().join("ignored.mp4");
+    client
+        .download_data_parallel(&video_info(8, content.len()), &path, 4, TransferOptions::new())
+        .await
+        .unwrap();
+    assert_eq!(std::fs::read(&path).unwrap(), content);
+    assert_eq!(requests.lock().unwrap().len(), 1);
+
+    // Не видео скачивается одним запросом без Range
+    let (client, requests) = range_client(&content, true);
+    let path = temp_dir.path().join("archive.zip");
+    let mut info = video_info(9, content.len());
+    info.extension = ".zip".to_string();
+    info.mime_type = "application/zip".to_string();
+    client
+        .download_data_parallel(&info, &path, 4, TransferOptions::new())
+        .await
+        .unwrap();
+    assert_eq!(std::fs::read(&path).unwrap(), content);
+    assert_eq!(*requests.lock().unwrap(), [None]);
+}
+
+#[tokio::test]
+async fn test_remote_media_reads_and_seeks() {
+    use tokio::io::{AsyncReadExt, AsyncSeekExt};
+
+    let content: Vec<u8> = (0..10_000u32).map(|i| (i % 249) as u8).collect();
+    let (client, requests) = range_client(&content, true);
+    let options = RemoteMediaOptions::new()
+        .with_block_size(1000)
+        .with_read_ahead(1)
+        .with_cache_blocks(4);
+    let mut media = client.open_media(DataId(8), options).await.unwrap();
+    assert_eq!(media.len(), 10_000);
+    assert_eq!(media.content_type(), Some("video/mp4"));
+
+    let mut all = Vec::new();
+    media.read_to_end(&mut all).await.unwrap();
+    assert_eq!(all, content);
+    // Каждый блок запрошен один раз, первый - при открытии
+    assert_eq!(requests.lock().unwrap().len(), 10);
+    assert_eq!(requests.lock().unwrap()[0].as_deref(), Some("bytes=0-"));
+
+    // Последний блок в кэше
+    media.seek(std::io::SeekFrom::End(-10)).await.unwrap();
+    let mut tail = Vec::new();
+    media.read_to_end(&mut tail).await.unwrap();
+    assert_eq!(tail, &content[9_990..]);
+    assert_eq!(requests.lock().unwrap().len(), 10);
+
+    // Вытесненные блоки запрашиваются снова
+    media.seek(std::io::SeekFrom::Start(4_500)).await.unwrap();
+    let mut window = vec![0u8; 1_000];
+    media.read_exact(&mut window).await.unwrap();
+    assert_eq!(window, &content[4_500..5_500]);
+    assert_eq!(media.position(), 5_500);
+    assert!(media.seek(std::io::SeekFrom::Current(-6_000)).await.is_err());
+}
+
+#[tokio::test]
+async fn test_remote_media_prefetches_in_background() {
+    use tokio::io::AsyncReadExt;
+
+    let content: Vec<u8> = (0..3_000u32).map(|i| (i % 101) as u8).collect();
+    let (client, requests) = range_client(&content, true);
+    let options = RemoteMediaOptions::new().with_block_size(1000).with_read_ahead(2);
+    let mut media = client.open_media(DataId(8), options).await.unwrap();
+
+    // Упреждение загружается без вызовов read
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    assert_eq!(
+        *requests.lock().unwrap(),
+        vec![
+            Some("bytes=0-".to_string()),
+            Some("bytes=1000-1999".to_string()),
+            Some("bytes=2000-2999".to_string()),
+        ]
+    );
+
+    // Медиа не заимствует клиента и работает в отдельной задаче
+    drop(client);
+    let all = tokio::spawn(async move {
+        let mut all = Vec::new();
+        media.read_to_end(&mut all).await.unwrap();
+        all
+    })
+    .await
+    .unwrap();
+    assert_eq!(all, content);
+    assert_eq!(requests.lock().unwrap().len(), 3);
+}
+
+#[tokio::test]
+async fn test_remote_media_keeps_read_ahead_failure() {
+    use tokio::io::AsyncReadExt;
+
+    // Второй блок сервер отдает без диапазона
+    let content: Vec<u8> = (0..3_000u32).map(|i| (i % 89) as u8).collect();
+    let requests = Arc::new(Mutex::new(Vec::new()));
+    let client = ZeroGalleryClient::builder("http://gallery.test")
+        .transport(RangeTransport {
+            content: bytes::Bytes::copy_from_slice(&content),
+            ranges: true,
+            ignored: Some(1000),
+            requests: requests.clone(),
+        })
+        .build()
+        .unwrap();
+    let options = RemoteMediaOptions::new().with_block_size(1000).with_read_ahead(1);
+    let mut media = client.open_media(DataId(8), options).await.unwrap();
+    tokio::time::sleep(Duration::from_millis(50)).await;
+
+    // Чтение первого блока не повторяет упавшее упреждение
+    let mut first = vec![0u8; 100];
+    for _ in 0..10 {
+        media.read_exact(&mut first).await.unwrap();
+    }
+    assert_eq!(first, &content[900..1_000]);
+    assert_eq!(requests.lock().unwrap().len(), 2);
+
+    // Ошибка возвращается при переходе к блоку, затем блок запрашивается снова
+    let error = media.read(&mut first).await.unwrap_err();
+    assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+    assert_eq!(requests.lock().unwrap().len(), 2);
+    assert!(media.read(&mut first).await.is_err());
+    assert_eq!(
+        requests.lock().unwrap()[2..],
+        [Some("bytes=1000-1999".to_string()), Some("bytes=2000-2999".to_string())]
+    );
+}
+
+#[tokio::test]
+async fn test_remote_media_without_ranges() {
+    let content: Vec<u8> = (0..5_000u32).map(|i| (i % 13) as u8).collect();
+    let (client, requests) = range_client(&content, false);
+    let error = client
+        .open_media(DataId(2), RemoteMediaOptions::new().with_block_size(1000))
+        .await
+        .unwrap_err();
+    assert!(matches!(&error, Error::Io(e) if e.kind() == std::io::ErrorKind::Unsupported));
+    assert_eq!(requests.lock().unwrap().len(), 1);
+}
+
+#[tokio::test]
+async fn test_stream_data_and_preview() {
+    use futures_util::StreamExt;
+    use tokio::io::AsyncReadExt;
+
+    let mut server = Server::new_async().await;
+    let url = server.url();
+
+    let _data = server
+        .mock("GET", "/api/data/3")
+        .with_status(200)
+        .with_header("content-type", "image/png")
+        .with_body(vec![9u8; 300_000])
+        .create_async()
+        .await;
+    let _preview = server
+        .mock("GET", "/api/preview/3")
+        .with_status(200)
+        .with_header("content-type", "image/jpeg")
+        .with_body("preview")
+        .create_async()
+        .await;
+
+    let client = create_test_client(&url);
+    let stream = client.stream_data(DataId(3)).await.unwrap();
+    assert_eq!(stream.content_length(), Some(300_000));
+    assert_eq!(stream.content_type(), Some("image/png"));
+    let chunks: Vec<_> = stream.collect().await;
+    let body: Vec<u8> = chunks.into_iter().flat_map(|chunk| chunk.unwrap()).collect();
+    assert_eq!(body, vec![9u8; 300_000]);
+
+    let preview = client.stream_preview(DataId(3)).await.unwrap();
+    assert_eq!(preview.content_type(), Some("image/jpeg"));
+    let mut text = String::new();
+    preview.into_reader().read_to_string(&mut text).await.unwrap();
+    assert_eq!(text, "preview");
+}
+
+#[tokio::test]
+async fn test_stream_read_timeout() {
+    use futures_util::StreamExt;
+
+    let client = ZeroGalleryClient::builder("http://gallery.test")
+        .transport(StalledTransport)
+        .read_timeout(Duration::from_millis(100))
+        .build()
+        .unwrap();
+    let mut stream = client.stream_data(DataId(3)).await.unwrap();
+    assert_eq!(stream.next().await.unwrap().unwrap().len(), 1024);
+    assert!(matches!(stream.next().await, Some(Err(Error::ReadTimeout(_)))));
+    assert!(stream.next().await.is_none());
+}
+
+#[tokio::test]
+async fn test_stream_video_range() {
+    let mut server = Server::new_async().await;
+    let url = server.url();
+
+    let _m = server
+        .mock("GET", "/api/data/4")
+        .match_header("range", "bytes=10-19")
+        .with_status(206)
+        .with_header("content-range", "bytes 10-19/100")
+        .with_header("content-type", "video/mp4")
+        .with_body("0123456789")
+        .create_async()
+        .await;
+
+    let client = create_test_client(&url);
+    let stream = client.stream_video(DataId(4), Some(10), Some(19)).await.unwrap();
+    assert_eq!(stream.status(), reqwest::StatusCode::PARTIAL_CONTENT);
+    assert_eq!(stream.content_range(), Some("bytes 10-19/100"));
+    assert_eq!(stream.content_length(), Some(10));
+    assert_eq!(stream.read_all().await.unwrap(), b"0123456789");
+}
+
+#[tokio::test]
+async fn test_upload_cancelled_before_start() {
+    let mut server = Server::new_async().await;
+    let url = server.url();
+
+    let m = server
+        .mock("POST", "/api/upload")
+        .with_status(200)
+        .with_body("1")
+        .expect(0)
+        .create_async()
+        .await;
+
+    let client = create_test_client(&url);
+    let token = CancellationToken::new();
+    token.cancel();
+    let result = client
+        .upload_file_data_with(b"data", "a.txt", UploadTarget::Public, TransferOptions::new().with_cancellation(token))
+        .await;
+    assert!(matches!(result, Err(Error::Cancelled)));
+    assert!(!result.unwrap_err().is_retryable());
+    m.assert_async().await;
+}
+
+#[tokio::test]
+async fn test_upload_source_streams_reader() {
+    use tokio::io::AsyncReadExt;
+
+    let mut server = Server::new_async().await;
+    let url = server.url();
+
+    // Сервер отвечает числом полученных байт содержимого
+    let m = server
+        .mock("POST", "/api/upload/4")
+        .with_status(200)
+        .with_body_from_request(|request| {
+            let body = request.body().unwrap();
+            body.iter().filter(|b| **b == b'x').count().to_string().into()
+        })
+        .expect(2)
+        .create_async()
+        .await;
+
+    let client = create_test_client(&url);
+    for length in [Some(300_000), None] {
+        let source = UploadSource::from_reader("big.bin", tokio::io::repeat(b'x').take(300_000), length);
+        assert!(!source.is_replayable());
+        let id = client
+            .upload_source(source, AlbumId(4), TransferOptions::new())
+            .await
+            .unwrap();
+        assert_eq!(id, DataId(300_000));
+    }
+    m.assert_async().await;
+}
+
+#[tokio::test]
+async fn test_upload_source_shorter_than_length() {
+    let mut server = Server::new_async().await;
+    let url = server.url();
+
+    let _m = server
+        .mock("POST", "/api/upload")
+        .with_status(200)
+        .with_body("1")
+        .create_async()
+        .await;
+
+    let client = create_test_client(&url);
+    let source = UploadSource::from_reader("short.bin", &b"ten bytes!"[..], Some(100));
+    let result = client.upload_source(source, UploadTarget::Public, TransferOptions::new()).await;
+    assert!(result.is_err());
+}
+
+#[tokio::test]
+async fn test_upload_progress() {
+    let mut server = Server::new_async().await;
+    let url = server.url();
+
+    let _m = server
+        .mock("POST", "/api/upload/2")
+        .with_status(200)
+        .with_body("[7, 8]")
+        .create_async()
+        .await;
+
+    let files = Arc::new(Mutex::new(std::collections::BTreeMap::new()));
+    let totals = Arc::new(Mutex::new(Vec::new()));
+    let (files_sink, totals_sink) = (files.clone(), totals.clone());
+    let options = TransferOptions::new()
+        .with_file_progress(Box::new(move |progress| {
+            files_sink
+                .lock()
+                .unwrap()
+                .insert(progress.file_name.to_string(), (progress.sent, progress.total));
+        }))
+        .with_progress(Box::new(move |sent, total| totals_sink.lock().unwrap().push((sent, total))));
+
+    let client = create_test_client(&url);
+    let sources = vec![
+        UploadSource::from_bytes("a.txt", vec![1u8; 1000]),
+        UploadSource::from_bytes("b.txt", vec![2u8; 500]),
+    ];
+    let ids = client.upload_sources(sources, AlbumId(2), options).await.unwrap();
+    assert_eq!(ids, vec![DataId(7), DataId(8)]);
+
+    let files = files.lock().unwrap();
+    assert_eq!(files["a.txt"], (1000, Some(1000)));
+    assert_eq!(files["b.txt"], (500, Some(500)));
+    let totals = totals.lock().unwrap();
+    assert_eq!(totals.last(), Some(&(1500, 1500)));
+    assert!(totals.windows(2).all(|pair| pair[0].0 <= pair[1].0));
+}
+
+#[tokio::test]
+async fn test_bulk_uploader_report() {
+    let mut server = Server::new_async().await;
+    let url = server.url();
+
+    let _ok = server
+        .mock("POST", "/api/upload/6")
+        .match_body(Matcher::Regex("filename=\"(a|b)\\.txt\"".to_string()))
+        .with_status(200)
+        .with_body_from_request(|request| {
+            let body = String::from_utf8_lossy(request.body().unwrap()).into_owned();
+            if body.contains("a.txt") { "1" } else { "2" }.into()
+        })
+        .create_async()
+        .await;
+    let _bad = server
+        .mock("POST", "/api/upload/6")
+        .match_body(Matcher::Regex("filename=\"bad\\.txt\"".to_string()))
+        .with_status(400)
+        .with_body("\"No files for upload\"")
+        .create_async()
+        .await;
+
+    let temp_dir = tempfile::tempdir().unwrap();
+    let a = temp_dir.path().join("a.txt");
+    std::fs::write(&a, "aaaa").unwrap();
+    let bad = temp_dir.path().join("bad.txt");
+    std::fs::write(&bad, "x").unwrap();
+    let missing = temp_dir.path().join("missing.txt");
+    let b = temp_dir.path().join("b.txt");
+    std::fs::write(&b, "bbbbbb").unwrap();
+
+    let client = create_test_client(&url);
+    let report = BulkUploader::new(&client, AlbumId(6))
+        .with_concurrency(2)
+        .upload_paths([&a, &bad, &missing, &b])
+        .await;
+
+    let results: Vec<_> = report.items.iter().map(|item| item.file_name.as_str()).collect();
+    assert_eq!(results, ["a.txt", "bad.txt", "missing.txt", "b.txt"]);
+    assert_eq!(report.items[0].result.as_ref().unwrap(), &DataId(1));
+    assert!(matches!(report.items[1].result, Err(Error::NoFilesForUpload(_))));
+    assert!(matches!(report.items[2].result, Err(Error::Io(_))));
+    assert_eq!(report.items[3].result.as_ref().unwrap(), &DataId(2));
+    assert_eq!((report.succeeded(), report.failed()), (2, 2));
+    assert_eq!(report.bytes_uploaded, 10);
+    assert!(!report.is_success());
+}
+
+#[tokio::test]
+async fn test_upload_multiple_files_single_id_response() {
+    let mut server = Server::new_async().await;
+    let url = server.url();
+
+    // Для формы из одного файла сервер возвращает число, а не массив
+    let _m = server
+        .mock("POST", "/api/upload")
+        .with_status(200)
+        .with_body("42")
+        .create_async()
+        .await;
+
+    let temp_dir = tempfile::tempdir().unwrap();
+    let path = temp_dir.path().join("only.txt");
+    std::fs::write(&path, "only").unwrap();
+
+    let client = create_test_client(&url);
+    let ids = client.upload_multiple_files(&[&path], UploadTarget::Public).await.unwrap();
+    assert_eq!(ids, vec![DataId(42)]);
+}
+
+/// Ответ загрузки с идентификатором по имени каждого файла формы (`f<N>.txt` -> N)
+fn ids_by_file_name(request: &mockito::Request) -> Vec<u8> {
+    let body = String::from_utf8_lossy(request.body().unwrap()).into_owned();
+    let ids: Vec<String> = body
+        .split("filename=\"f")
+        .skip(1)
+        .map(|rest| rest.split('.').next().unwrap().to_string())
+        .collect();
+    if ids.len() == 1 {
+        ids[0].clone().into()
+    } else {
+        format!("[{}]", ids.join(",")).into()
+    }
+}
+
+#[tokio::test]
+async fn test_upload_batched_keeps_input_order() {
+    let mut server = Server::new_async().await;
+    let url = server.url();
+
+    let m = server
+        .mock("POST", "/api/upload/3")
+        .with_status(200)
+        .with_body_from_request(ids_by_file_name)
+        .expect(3)
+        .create_async()
+        .await;
+
+    let sources: Vec<_> = (1..=5)
+        .map(|i| UploadSource::from_bytes(format!("f{}.txt", i), vec![b'x'; 4]))
+        .collect();
+    let client = create_test_client(&url);
+    let planner = UploadBatchPlanner::new().with_max_bytes(8);
+    let ids = client
+        .upload_batched(sources, AlbumId(3), &planner, TransferOptions::new())
+        .await
+        .unwrap();
+
+    assert_eq!(ids, (1..=5).map(DataId).collect::<Vec<_>>());
+    m.assert_async().await;
+}
+
+#[tokio::test]
+async fn test_upload_batched_splits_rejected_form() {
+    let mut server = Server::new_async().await;
+    let url = server.url();
+
+    let too_large = server
+        .mock("POST", "/api/upload")
+        .match_body(Matcher::Regex("(?s)f1\\.txt.*f2\\.txt".to_string()))
+        .with_status(413)
+        .expect(1)
+        .create_async()
+        .await;
+    let ok = server
+        .mock("POST", "/api/upload")
+        .match_body(Matcher::Regex("filename=\"f\\d\\.txt\"".to_string()))
+        .with_status(200)
+        .with_body_from_request(ids_by_file_name)
+        .expect(2)
+        .create_async()
+        .await;
+
+    let sources = vec![
+        UploadSource::from_bytes("f1.txt", &b"one"[..]),
+        UploadSource::from_bytes("f2.txt", &b"two"[..]),
+    ];
+    let client = create_test_client(&url);
+    let ids = client
+        .upload_batched(sources, UploadTarget::Public, &UploadBatchPlanner::new(), TransferOptions::new())
+        .await
+        .unwrap();
+
+    assert_eq!(ids, vec![DataId(1), DataId(2)]);
+    too_large.assert_async().await;
+    ok.assert_async().await;
+}
+
+// TLS: локальный сервер на rustls с сертификатами от тестового CA
+#[cfg(any(feature = "default-tls", feature = "rustls-tls"))]
+mod tls {
+    use super::*;
+    use rcgen::{BasicConstraints, Certificate, CertificateParams, DnType, IsCa};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio_rustls::rustls;
+    use zerogallery::ClientIdentity;
+
+    struct TestPki {
+        ca_pem: String,
+        ca_der: Vec<u8>,
+        server_der: Vec<u8>,
+        server_key_der: Vec<u8>,
+        client_pem: String,
+        client_key_pem: String,
+    }
+
+    fn generate_pki() -> TestPki {
+        let mut ca_params = CertificateParams::new(Vec::new());
+        ca_params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+        ca_params
+            .distinguished_name
+            .push(DnType::CommonName, "ZeroGallery Test CA");
+        let ca = Certificate::from_params(ca_params).unwrap();
+
+        let server = Certificate::from_params(CertificateParams::new(vec!["localhost".to_string()])).unwrap();
+        let mut client_params = CertificateParams::new(Vec::new());
+        client_params
+            .distinguished_name
+            .push(DnType::CommonName, "gallery-client");
+        let client = Certificate::from_params(client_params).unwrap();
+
+        TestPki {
+            ca_pem: ca.serialize_pem().unwrap(),
+            ca_der: ca.serialize_der().unwrap(),
+            server_der: server.serialize_der_with_signer(&ca).unwrap(),
+            server_key_der: server.serialize_private_key_der(),
+            client_pem: client.serialize_pem_with_signer(&ca).unwrap(),
+            client_key_pem: client.serialize_private_key_pem(),
+        }
+    }
+
+    /// Запустить TLS-сервер, который отвечает версией API на любой запрос
+    async fn spawn_tls_server(pki: &TestPki, require_client_cert: bool) -> String {
+        let builder = rustls::ServerConfig::builder().with_safe_defaults();
+        let builder = if require_client_cert {
+            let mut roots = rustls::RootCertStore::empty();
+            roots.add(&rustls::Certificate(pki.ca_der.clone())).unwrap();
+            builder.with_client_cert_verifier(
+                rustls::server::AllowAnyAuthenticatedClient::new(roots).boxed(),
+            )
+        } else {
+            builder.with_no_client_auth()
+        };
+        let config = builder
+            .with_single_cert(
+                vec![rustls::Certificate(pki.server_der.clone())],
+                rustls::PrivateKey(pki.server_key_der.clone()),
+            )
+            .unwrap();
+        let acceptor = tokio_rustls::TlsAcceptor::from(Arc::new(config));
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            loop {
+                let (socket, _) = listener.accept().await.unwrap();
+                let acceptor = acceptor.clone();
+                tokio::spawn(async move {
+                    let Ok(mut stream) = acceptor.accept(socket).await else {
+                        return;
+                    };
+                    let mut request = Vec::new();
+                    let mut buf = [0u8; 1024];
+                    while !request.windows(4).any(|w| w == b"\r\n\r\n") {
+                        match stream.read(&mut buf).await {
+                            Ok(0) | Err(_) => return,
+                            Ok(n) => request.extend_from_slice(&buf[..n]),
+                        }
+                    }
+                    let response = "HTTP/1.1 200 OK\r\nContent-Length: 3\r\nConnection: close\r\n\r\n1.0";
+                    let _ = stream.write_all(response.as_bytes()).await;
+                    let _ = stream.shutdown().await;
+                });
+            }
+        });
+        format!("https://localhost:{}", port)
+    }
+
+    #[tokio::test]
+    async fn test_tls_custom_ca() {
+        let pki = generate_pki();
+        let url = spawn_tls_server(&pki, false).await;
+
+        let client = ZeroGalleryClient::builder(&url)
+            .add_root_certificate_pem(pki.ca_pem.clone())
+            .build()
+            .unwrap();
+        assert_eq!(client.get_version().await.unwrap(), "1.0");
+
+        let untrusted = ZeroGalleryClient::builder(&url).build().unwrap();
+        assert!(matches!(untrusted.get_version().await, Err(Error::Request(_))));
+    }
+
+    #[tokio::test]
+    async fn test_tls_client_identity() {
+        let pki = generate_pki();
+        let url = spawn_tls_server(&pki, true).await;
+
+        let client = ZeroGalleryClient::builder(&url)
+            .add_root_certificate_pem(pki.ca_pem.clone())
+            .client_identity(ClientIdentity::from_pem(
+                pki.client_pem.clone(),
+                pki.client_key_pem.clone(),
+            ))
+            .build()
+            .unwrap();
+        assert_eq!(client.get_version().await.unwrap(), "1.0");
+
+        let anonymous = ZeroGalleryClient::builder(&url)
+            .add_root_certificate_pem(pki.ca_pem.clone())
+            .build()
+            .unwrap();
+        assert!(anonymous.get_version().await.is_err());
+    }
+
+    #[cfg(feature = "rustls-tls")]
+    #[tokio::test]
+    async fn test_tls_certificate_pinning() {
+        use sha2::{Digest, Sha256};
+        use zerogallery::TlsBackend;
+
+        let pki = generate_pki();
+        let url = spawn_tls_server(&pki, false).await;
+        let fingerprint: String = Sha256::digest(&pki.server_der)
+            .iter()
+            .map(|b| format!("{:02X}", b))
+            .collect::<Vec<_>>()
+            .join(":");
+
+        let pinned = ZeroGalleryClient::builder(&url)
+            .tls_backend(TlsBackend::Rustls)
+            .add_root_certificate_pem(pki.ca_pem.clone())
+            .pin_certificate_sha256(fingerprint)
+            .build()
+            .unwrap();
+        assert_eq!(pinned.get_version().await.unwrap(), "1.0");
+
+        let mismatched = ZeroGalleryClient::builder(&url)
+            .tls_backend(TlsBackend::Rustls)
+            .add_root_certificate_pem(pki.ca_pem.clone())
+            .pin_certificate_sha256("00".repeat(32))
+            .build()
+            .unwrap();
+        assert!(mismatched.get_version().await.is_err());
+
+        let malformed = ZeroGalleryClient::builder(&url)
+            .tls_backend(TlsBackend::Rustls)
+            .pin_certificate_sha256("not-a-fingerprint")
+            .build();
+        assert!(matches!(malformed, Err(Error::Config(_))));
+    }
+}
+
+#[cfg(feature = "tower")]
+mod middleware {
+    use super::*;
+    use std::sync::atomic::AtomicUsize;
+    use tower::limit::ConcurrencyLimitLayer;
+    use tower::timeout::TimeoutLayer;
+    use tower::util::MapRequestLayer;
+    use tower::ServiceExt;
+
+    #[tokio::test]
+    async fn test_layers_wrap_every_call() {
+        let mut server = Server::new_async().await;
+        let url = server.url();
+
+        let _albums = server
+            .mock("GET", "/api/albums")
+            .match_header("x-request-id", "1")
+            .match_header("x-layer", "outer")
+            .with_status(200)
+            .with_body("[]")
+            .create_async()
+            .await;
+        let _data = server
+            .mock("GET", "/api/data/7")
+            .match_header("x-request-id", "2")
+            .with_status(200)
+            .with_body("content")
+            .create_async()
+            .await;
+
+        let counter = Arc::new(AtomicUsize::new(0));
+        let ids = counter.clone();
+        let client = ZeroGalleryClient::builder(&url)
+            .layer(MapRequestLayer::new(|mut request: ApiRequest| {
+                // Внешний слой выполняется первым и видит запрос без заголовков внутреннего
+                assert!(!request.headers.contains_key("x-request-id"));
+                request.headers.insert("x-layer", "outer".parse().unwrap());
+                request
+            }))
+            .layer(MapRequestLayer::new(move |mut request: ApiRequest| {
+                let id = ids.fetch_add(1, Ordering::SeqCst) + 1;
+                request.headers.insert("x-request-id", id.to_string().parse().unwrap());
+                request
+            }))
+            .layer(ConcurrencyLimitLayer::new(1))
+            .build()
+            .unwrap();
+
+        assert!(client.get_albums().await.unwrap().is_empty());
+        let temp_dir = tempfile::tempdir().unwrap();
+        let path = temp_dir.path().join("data.bin");
+        client.download_data(DataId(7), &path, None).await.unwrap();
+        assert_eq!(std::fs::read(&path).unwrap(), b"content");
+        assert_eq!(counter.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn test_timeout_layer() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let (_socket, _) = listener.accept().await.unwrap();
+            tokio::time::sleep(Duration::from_secs(10)).await;
+        });
+
+        let client = ZeroGalleryClient::builder(format!("http://{}", addr))
+            .layer(TimeoutLayer::new(Duration::from_millis(200)))
+            .retry_policy(RetryPolicy::disabled())
+            .build()
+            .unwrap();
+        match client.get_version().await {
+            Err(Error::Transport(error)) => assert!(error.is::<tower::timeout::error::Elapsed>()),
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_client_errors_pass_through_layers() {
+        let client = ZeroGalleryClient::builder("http://gallery.test")
+            .transport(FailingTransport)
+            .layer(ConcurrencyLimitLayer::new(4))
+            .build()
+            .unwrap();
+        match client.get_version().await {
+            Err(Error::Transport(error)) => assert!(error.is::<std::io::Error>()),
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_client_service() {
+        let transport = Arc::new(RecordingTransport {
+            chunks: vec!["1.0"],
+            ..Default::default()
+        });
+        let client = ZeroGalleryClient::builder("http://gallery.test")
+            .transport(transport.clone())
+            .build()
+            .unwrap();
+
+        let request = ApiRequest::new("raw", reqwest::Method::GET, "http://gallery.test/api/version");
+        let response = client.service().oneshot(request).await.unwrap();
+        assert_eq!(response.status, reqwest::StatusCode::OK);
+        assert_eq!(transport.requests.lock().unwrap()[0].0, "raw");
+    }
+}
+
+#[cfg(feature = "tracing")]
+mod tracing_spans {
+    use super::*;
+    use tracing::field::{Field, Visit};
+    use tracing::span::{Attributes, Id, Record};
+    use tracing_subscriber::layer::{Context, SubscriberExt};
+    use tracing_subscriber::Layer;
+
+    /// Слой, собирающий поля спанов `zerogallery.request`
+    #[derive(Clone, Default)]
+    struct FieldRecorder {
+        fields: Arc<Mutex<Vec<(String, String)>>>,
+    }
+
+    impl Visit for FieldRecorder {
+        fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+            self.fields
+                .lock()
+                .unwrap()
+                .push((field.name().to_string(), format!("{:?}", value)));
+        }
+    }
+
+    impl<S: tracing::Subscriber> Layer<S> for FieldRecorder {
+        fn on_new_span(&self, attrs: &Attributes<'_>, _id: &Id, _ctx: Context<'_, S>) {
+            if attrs.metadata().name() == "zerogallery.request" {
+                attrs.record(&mut self.clone());
+            }
+        }
+
+        fn on_record(&self, _id: &Id, values: &Record<'_>, _ctx: Context<'_, S>) {
+            values.record(&mut self.clone());
+        }
+    }
+
+    impl FieldRecorder {
+        fn get(&self, name: &str) -> Option<String> {
+            self.fields
+                .lock()
+                .unwrap()
+                .iter()
+                .rev()
+                .find(|(field, _)| field == name)
+                .map(|(_, value)| value.clone())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_request_span_fields() {
+        let mut server = Server::new_async().await;
+        let url = server.url();
+
+        let _m = server
+            .mock("GET", "/api/data/12")
+            .with_status(200)
+            .with_body("twelve bytes")
+            .create_async()
+            .await;
+
+        let recorder = FieldRecorder::default();
+        let _guard = tracing::subscriber::set_default(tracing_subscriber::registry().with(recorder.clone()));
+
+        let client = create_test_client(&url);
+        assert_eq!(client.get_data(DataId(12)).await.unwrap(), b"twelve bytes");
+
+        assert_eq!(recorder.get("operation").as_deref(), Some("\"get_data\""));
+        assert_eq!(recorder.get("http.method").as_deref(), Some("GET"));
+        assert_eq!(recorder.get("data_id").as_deref(), Some("12"));
+        assert_eq!(recorder.get("http.status_code").as_deref(), Some("200"));
+        assert_eq!(recorder.get("response_bytes").as_deref(), Some("12"));
+        assert!(recorder.get("latency_ms").is_some());
+    }
+
+    #[cfg(feature = "opentelemetry")]
+    #[tokio::test]
+    async fn test_traceparent_injected() {
+        use opentelemetry::trace::TracerProvider as _;
+
+        let mut server = Server::new_async().await;
+        let url = server.url();
+
+        let _m = server
+            .mock("GET", "/api/albums")
+            .match_header("traceparent", Matcher::Regex("^00-[0-9a-f]{32}-[0-9a-f]{16}-01$".to_string()))
+            .with_status(200)
+            .with_body("[]")
+            .create_async()
+            .await;
+
+        let provider = opentelemetry_sdk::trace::SdkTracerProvider::builder().build();
+        let subscriber = tracing_subscriber::registry()
+            .with(tracing_opentelemetry::layer().with_tracer(provider.tracer("zerogallery-tests")));
+        let _guard = tracing::subscriber::set_default(subscriber);
+
+        let client = create_test_client(&url);
+        assert!(client.get_albums().await.unwrap().is_empty());
+    }
+}
+
+#[cfg(feature = "blocking")]
+mod blocking_client {
+    use super::*;
+    use zerogallery::ZeroGalleryBlockingClient;
+
+    #[test]
+    fn test_blocking_client_mirrors_async() {
+        let mut server = Server::new();
+        let url = server.url();
+
+        let _albums = server
+            .mock("GET", "/api/albums")
+            .with_status(200)
+            .with_body(r#"[{"id":2,"imagePreviewId":-1,"name":"trip","description":"","isProtected":false}]"#)
+            .create();
+        let _upload = server
+            .mock("POST", "/api/upload/2")
+            .with_status(200)
+            .with_body("17")
+            .create();
+        let _data = server
+            .mock("GET", "/api/data/17")
+            .with_status(200)
+            .with_header("content-length", "5")
+            .with_body("hello")
+            .create();
+
+        let client = ZeroGalleryBlockingClient::new(&url);
+        let albums = client.get_albums().unwrap();
+        assert_eq!(albums[0].id, AlbumId(2));
+        let id = client.upload_file_data(b"hello", "a.txt", albums[0].id).unwrap();
+        assert_eq!(id, DataId(17));
+        assert_eq!(client.get_data(id).unwrap(), b"hello");
+
+        let temp_dir = tempfile::tempdir().unwrap();
+        let path = temp_dir.path().join("a.txt");
+        let reported = Arc::new(Mutex::new(Vec::new()));
+        let sink = reported.clone();
+        client
+            .download_data(
+                id,
+                &path,
+                Some(Box::new(move |done, total| sink.lock().unwrap().push((done, total)))),
+            )
+            .unwrap();
+        assert_eq!(std::fs::read(&path).unwrap(), b"hello");
+        assert_eq!(reported.lock().unwrap().last(), Some(&(5, 5)));
+        assert_eq!(client.client().metrics().snapshot().operations["get_albums"].requests, 1);
+    }
+
+    #[test]
+    fn test_blocking_client_errors() {
+        let mut server = Server::new();
+        let url = server.url();
+
+        let _m = server
+            .mock("GET", "/api/data/404")
+            .with_status(404)
+            .create();
+
+        let client = ZeroGalleryClient::builder(&url).build_blocking().unwrap();
+        assert!(matches!(client.get_data(DataId(404)), Err(Error::NotFound(_))));
+    }
+
+    #[test]
+    fn test_blocking_streams() {
+        use std::io::Read;
+
+        let mut server = Server::new();
+        let url = server.url();
+
+        let _data = server
+            .mock("GET", "/api/data/3")
+            .with_status(200)
+            .with_header("content-type", "image/png")
+            .with_body(vec![9u8; 300_000])
+            .create();
+        let _preview = server
+            .mock("GET", "/api/preview/3")
+            .with_status(200)
+            .with_body("preview")
+            .create();
+        let _video = server
+            .mock("GET", "/api/data/4")
+            .match_header("range", "bytes=10-19")
+            .with_status(206)
+            .with_header("content-range", "bytes 10-19/100")
+            .with_body("0123456789")
+            .create();
+
+        let client = ZeroGalleryBlockingClient::new(&url);
+        let mut data = client.stream_data(DataId(3)).unwrap();
+        assert_eq!(data.content_length(), Some(300_000));
+        assert_eq!(data.content_type(), Some("image/png"));
+        let mut body = Vec::new();
+        data.read_to_end(&mut body).unwrap();
+        assert_eq!(body, vec![9u8; 300_000]);
+
+        let mut text = String::new();
+        client.stream_preview(DataId(3)).unwrap().read_to_string(&mut text).unwrap();
+        assert_eq!(text, "preview");
+
+        let mut video = client.stream_video(DataId(4), Some(10), Some(19)).unwrap();
+        assert_eq!(video.status(), reqwest::StatusCode::PARTIAL_CONTENT);
+        assert_eq!(video.content_range(), Some("bytes 10-19/100"));
+        let mut head = [0u8; 4];
+        video.read_exact(&mut head).unwrap();
+        assert_eq!(&head, b"0123");
+        let mut rest = Vec::new();
+        video.read_to_end(&mut rest).unwrap();
+        assert_eq!(rest, b"456789");
+    }
+
+    #[test]
+    fn test_blocking_remote_media() {
+        use std::io::{Read, Seek};
+
+        let content: Vec<u8> = (0..5_000u32).map(|i| (i % 97) as u8).collect();
+        let (client, requests) = range_client(&content, true);
+        let client = ZeroGalleryBlockingClient::from_async(client).unwrap();
+        let options = RemoteMediaOptions::new().with_block_size(1000).with_read_ahead(1);
+        let mut media = client.open_media(DataId(8), options).unwrap();
+        assert_eq!(media.len(), 5_000);
+
+        let mut all = Vec::new();
+        media.read_to_end(&mut all).unwrap();
+        assert_eq!(all, content);
+        assert_eq!(requests.lock().unwrap().len(), 5);
+
+        media.seek(std::io::SeekFrom::Start(1_500)).unwrap();
+        let mut window = vec![0u8; 100];
+        media.read_exact(&mut window).unwrap();
+        assert_eq!(window, &content[1_500..1_600]);
+        assert_eq!(media.position(), 1_600);
+
+        // Читатель переживает клиента
+        drop(client);
+        media.seek(std::io::SeekFrom::End(-10)).unwrap();
+        let mut tail = Vec::new();
+        media.read_to_end(&mut tail).unwrap();
+        assert_eq!(tail, &content[4_990..]);
+    }
+}
+
+#[cfg(feature = "progress")]
+mod progress_bars {
+    use super::*;
+    use indicatif::{MultiProgress, ProgressDrawTarget};
+    use zerogallery::TransferProgressBars;
+
+    #[tokio::test]
+    async fn test_progress_bars_follow_transfers() {
+        let mut server = Server::new_async().await;
+        let url = server.url();
+
+        let _upload = server
+            .mock("POST", "/api/upload")
+            .with_status(200)
+            .with_body("[1, 2]")
+            .create_async()
+            .await;
+        let _download = server
+            .mock("GET", "/api/data/1")
+            .with_status(200)
+            .with_header("content-length", "2048")
+            .with_body(vec![0u8; 2048])
+            .create_async()
+            .await;
+
+        let client = create_test_client(&url);
+        let multi = MultiProgress::with_draw_target(ProgressDrawTarget::hidden());
+        let bars = TransferProgressBars::with_multi_progress(multi.clone(), "upload").with_file_bars(true);
+        let sources = vec![
+            UploadSource::from_bytes("a.bin", vec![0u8; 300]),
+            UploadSource::from_bytes("b.bin", vec![0u8; 700]),
+        ];
+        client
+            .upload_sources(sources, UploadTarget::Public, bars.attach(TransferOptions::new()))
+            .await
+            .unwrap();
+        bars.finish();
+        assert_eq!(bars.total().length(), Some(1000));
+        assert_eq!(bars.total().position(), 1000);
+
+        let bars = TransferProgressBars::with_multi_progress(multi, "download");
+        let temp_dir = tempfile::tempdir().unwrap();
+        client
+            .download_data_with(DataId(1), temp_dir.path().join("1.bin"), bars.attach(TransferOptions::new()))
+            .await
+            .unwrap();
+        assert_eq!(bars.total().position(), 2048);
+    }
 }
 
 // Бенчмарки
 #[cfg(test)]
+#[allow(dead_code)]
 mod benches {
     use super::*;
-    use criterion::{criterion_group, criterion_main, Criterion};
+    use criterion::{criterion_group, Criterion};
     
     fn bench_format_size(c: &mut Criterion) {
         let data = DataInfo {
-            id: 1,
-            album_id: 1,
+            id: DataId(1),
+            album_id: Some(AlbumId(1)),
             size: 1048576,
             created_timestamp: 0,
             name: String::new(),
@@ -342,6 +2690,7 @@ mod benches {
             description: String::new(),
             mime_type: String::new(),
             tags: String::new(),
+            has_preview: false,
         };
         
         c.bench_function("format_size", |b| {